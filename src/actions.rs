@@ -0,0 +1,118 @@
+use notify_rust::{ActionResponse, Notification};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    Snooze,
+    Dismiss,
+    Suspend,
+    Settings,
+}
+
+impl Action {
+    const ALL: [Action; 4] = [
+        Action::Snooze,
+        Action::Dismiss,
+        Action::Suspend,
+        Action::Settings,
+    ];
+
+    pub fn id(self) -> &'static str {
+        match self {
+            Action::Snooze => "snooze",
+            Action::Dismiss => "dismiss",
+            Action::Suspend => "suspend",
+            Action::Settings => "settings",
+        }
+    }
+
+    pub fn from_id(id: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|action| action.id() == id)
+    }
+
+    fn label(self, config: &ActionsConfig) -> String {
+        match self {
+            Action::Snooze => format!("Snooze {} min", config.snooze_minutes),
+            Action::Dismiss => "Dismiss until next level".to_string(),
+            Action::Suspend => "Suspend now".to_string(),
+            Action::Settings => "Open settings".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ActionsConfig {
+    pub enabled: bool,
+    pub snooze_minutes: u32,
+    pub suspend_command: Vec<String>,
+    pub settings_command: Vec<String>,
+}
+
+impl Default for ActionsConfig {
+    fn default() -> Self {
+        ActionsConfig {
+            enabled: true,
+            snooze_minutes: 10,
+            suspend_command: vec!["systemctl".to_string(), "suspend".to_string()],
+            settings_command: vec!["gnome-control-center".to_string(), "power".to_string()],
+        }
+    }
+}
+
+pub fn add_actions(notification: &mut Notification, config: &ActionsConfig) {
+    if !config.enabled {
+        return;
+    }
+
+    for action in Action::ALL {
+        if (action == Action::Suspend && config.suspend_command.is_empty())
+            || (action == Action::Settings && config.settings_command.is_empty())
+        {
+            continue;
+        }
+
+        notification.action(action.id(), &action.label(config));
+    }
+}
+
+/// Waits until an action of the notification is invoked or it gets closed.
+/// The D-Bus wait blocks, so it runs off the event loop.
+pub async fn wait_for_action(id: u32) -> Option<Action> {
+    let res = tokio::task::spawn_blocking(move || {
+        let mut invoked = None;
+
+        notify_rust::handle_action(id, |response| {
+            if let ActionResponse::Custom(action) = response {
+                invoked = Action::from_id(action);
+            }
+        });
+
+        invoked
+    })
+    .await;
+
+    match res {
+        Ok(out) => out,
+        Err(e) => {
+            log::debug!("notification {id} action listener failed: {e}");
+            None
+        }
+    }
+}
+
+pub async fn run_command(command: &[String]) {
+    let Some((program, args)) = command.split_first() else {
+        return;
+    };
+
+    match tokio::process::Command::new(program)
+        .args(args)
+        .status()
+        .await
+    {
+        Ok(status) if status.success() => log::debug!("{program} exited successfully"),
+        Ok(status) => log::warn!("{program} failed: {status}"),
+        Err(e) => log::error!("cannot run {program}: {e}"),
+    }
+}
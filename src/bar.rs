@@ -0,0 +1,243 @@
+use crate::{battery, config::Config, helper};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum BarFormat {
+    Waybar,
+    I3blocks,
+    Polybar,
+    #[default]
+    Plain,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BarConfig {
+    pub format: BarFormat,
+    pub template: String,
+    pub tooltip: String,
+    pub icons: Vec<String>,
+    pub charging_icon: String,
+    pub low_color: String,
+}
+
+impl Default for BarConfig {
+    fn default() -> Self {
+        BarConfig {
+            format: BarFormat::Plain,
+            template: "{icon} {percent}%".to_string(),
+            tooltip: "BAT{battery}: {percent}% ({status})".to_string(),
+            icons: ["\u{f244}", "\u{f243}", "\u{f242}", "\u{f241}", "\u{f240}"]
+                .map(String::from)
+                .to_vec(),
+            charging_icon: "\u{f0e7}".to_string(),
+            low_color: "#ff5555".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct BarState {
+    percent: u32,
+    status: battery::ChargeStatus,
+}
+
+#[derive(Serialize)]
+struct WaybarLine<'a> {
+    text: &'a str,
+    tooltip: &'a str,
+    class: &'a str,
+    percentage: u32,
+}
+
+fn icon(bar: &BarConfig, state: &BarState) -> String {
+    if state.status == battery::ChargeStatus::Charging && !bar.charging_icon.is_empty() {
+        return bar.charging_icon.clone();
+    }
+
+    if bar.icons.is_empty() {
+        return String::new();
+    }
+
+    let index = (state.percent.min(100) as usize * bar.icons.len()) / 101;
+    bar.icons[index].clone()
+}
+
+fn class(config: &Config, state: &BarState) -> &'static str {
+    match state.status {
+        battery::ChargeStatus::Charging => "charging",
+        battery::ChargeStatus::NotCharging => "full",
+        _ if state.percent <= config.low_battery_percent => "critical",
+        battery::ChargeStatus::Discharging => "discharging",
+        battery::ChargeStatus::Unknown => "unknown",
+    }
+}
+
+fn format_line(config: &Config, format: BarFormat, state: &BarState) -> String {
+    let bar = &config.bar;
+    let values = [
+        ("icon", icon(bar, state)),
+        ("percent", state.percent.to_string()),
+        ("status", state.status.to_string()),
+        ("battery", config.battery_id.to_string()),
+    ];
+
+    let text = helper::render_template(&bar.template, &values);
+    let tooltip = helper::render_template(&bar.tooltip, &values);
+    let class = class(config, state);
+    let low = class == "critical";
+
+    match format {
+        BarFormat::Waybar => serde_json::to_string(&WaybarLine {
+            text: &text,
+            tooltip: &tooltip,
+            class,
+            percentage: state.percent,
+        })
+        .unwrap(),
+        BarFormat::I3blocks => {
+            let color = if low { bar.low_color.as_str() } else { "" };
+            format!("{text}\n{text}\n{color}")
+        }
+        BarFormat::Polybar if low => format!("%{{F{}}}{text}%{{F-}}", bar.low_color),
+        BarFormat::Polybar | BarFormat::Plain => text,
+    }
+}
+
+fn read_state(battery_id: u32) -> Option<BarState> {
+    Some(BarState {
+        percent: battery::Battery::get_live_percent(battery_id).ok()?,
+        status: battery::Battery::get_live_status(battery_id).ok()?,
+    })
+}
+
+pub async fn run(config: &Config, format: BarFormat) -> notify::Result<()> {
+    let (_percent_watcher, mut percent_rx) =
+        helper::watch_file(battery::percent_path(config.battery_id))?;
+    let (_status_watcher, mut status_rx) =
+        helper::watch_file(battery::status_path(config.battery_id))?;
+
+    let mut last_state = None;
+
+    loop {
+        match read_state(config.battery_id) {
+            Some(state) if last_state.as_ref() != Some(&state) => {
+                println!("{}", format_line(config, format, &state));
+                last_state = Some(state);
+            }
+            Some(_) => {}
+            None => log::error!("BAT{} does not exist!", config.battery_id),
+        }
+
+        let res = tokio::select! {
+            Some(res) = percent_rx.recv() => res,
+            Some(res) = status_rx.recv() => res,
+            else => break,
+        };
+
+        if let Err(e) = res {
+            log::error!("watch error: {:?}", e);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use battery::ChargeStatus;
+
+    fn config() -> Config {
+        let mut config = Config::default();
+        config.battery_id = 1;
+        config.low_battery_percent = 15;
+        config.bar.icons = ["0", "1", "2", "3", "4"].map(String::from).to_vec();
+        config.bar.charging_icon = "C".to_string();
+        config
+    }
+
+    fn state(percent: u32, status: ChargeStatus) -> BarState {
+        BarState { percent, status }
+    }
+
+    #[test]
+    fn waybar_line() {
+        let line = format_line(
+            &config(),
+            BarFormat::Waybar,
+            &state(42, ChargeStatus::Discharging),
+        );
+        let json: serde_json::Value = serde_json::from_str(&line).unwrap();
+
+        assert_eq!(json["text"], "2 42%");
+        assert_eq!(json["tooltip"], "BAT1: 42% (Discharging)");
+        assert_eq!(json["class"], "discharging");
+        assert_eq!(json["percentage"], 42);
+
+        let line = format_line(
+            &config(),
+            BarFormat::Waybar,
+            &state(15, ChargeStatus::Discharging),
+        );
+        let json: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(json["class"], "critical");
+    }
+
+    #[test]
+    fn i3blocks_lines() {
+        let config = config();
+
+        assert_eq!(
+            format_line(
+                &config,
+                BarFormat::I3blocks,
+                &state(10, ChargeStatus::Discharging)
+            ),
+            "0 10%\n0 10%\n#ff5555"
+        );
+        assert_eq!(
+            format_line(
+                &config,
+                BarFormat::I3blocks,
+                &state(10, ChargeStatus::Charging)
+            ),
+            "C 10%\nC 10%\n"
+        );
+    }
+
+    #[test]
+    fn polybar_colours_only_when_low() {
+        let config = config();
+
+        assert_eq!(
+            format_line(
+                &config,
+                BarFormat::Polybar,
+                &state(10, ChargeStatus::Discharging)
+            ),
+            "%{F#ff5555}0 10%%{F-}"
+        );
+        assert_eq!(
+            format_line(
+                &config,
+                BarFormat::Polybar,
+                &state(90, ChargeStatus::Discharging)
+            ),
+            "4 90%"
+        );
+    }
+
+    #[test]
+    fn ramp_covers_the_whole_range() {
+        let config = config();
+        let ramp = |percent| icon(&config.bar, &state(percent, ChargeStatus::Discharging));
+
+        assert_eq!(ramp(0), "0");
+        assert_eq!(ramp(config.low_battery_percent), "0");
+        assert_eq!(ramp(100), "4");
+        assert_eq!(ramp(150), "4");
+        assert_eq!(icon(&config.bar, &state(0, ChargeStatus::Charging)), "C");
+    }
+}
@@ -1,17 +1,46 @@
 use regex::Regex;
-use std::{path::Path, process::exit};
+use serde::{Deserialize, Serialize};
+use std::{
+    path::{Path, PathBuf},
+    process::exit,
+    sync::OnceLock,
+};
 
 const POWER_SUPPLY_PATH: &str = "/sys/class/power_supply";
 
-pub fn percent_path(id: u32) -> String {
-    format!("{POWER_SUPPLY_PATH}/BAT{id}/capacity")
+static POWER_SUPPLY_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// Point every reader at another power_supply tree, e.g. a simulated one.
+/// Has to happen before the first read.
+pub fn set_power_supply_dir(dir: PathBuf) {
+    if POWER_SUPPLY_DIR.set(dir).is_err() {
+        log::warn!("power supply directory already in use");
+    }
+}
+
+pub fn power_supply_dir() -> &'static Path {
+    POWER_SUPPLY_DIR.get_or_init(|| PathBuf::from(POWER_SUPPLY_PATH))
 }
 
-pub fn status_path(id: u32) -> String {
-    format!("{POWER_SUPPLY_PATH}/BAT{id}/status")
+pub fn percent_path(id: u32) -> PathBuf {
+    attribute_path(&format!("BAT{id}"), "capacity")
 }
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+pub fn status_path(id: u32) -> PathBuf {
+    attribute_path(&format!("BAT{id}"), "status")
+}
+
+pub fn attribute_path(name: &str, attribute: &str) -> PathBuf {
+    power_supply_dir().join(name).join(attribute)
+}
+
+pub fn read_attribute(name: &str, attribute: &str) -> std::io::Result<String> {
+    Ok(std::fs::read_to_string(attribute_path(name, attribute))?
+        .trim_end()
+        .to_string())
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
 pub enum ChargeStatus {
     Charging,
     Discharging,
@@ -19,6 +48,17 @@ pub enum ChargeStatus {
     Unknown,
 }
 
+impl std::fmt::Display for ChargeStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Charging => "Charging",
+            Self::NotCharging => "Not charging",
+            Self::Discharging => "Discharging",
+            Self::Unknown => "Unknown",
+        })
+    }
+}
+
 impl From<&str> for ChargeStatus {
     fn from(value: &str) -> Self {
         match value {
@@ -44,8 +84,7 @@ pub enum BatteryError {
 
 impl Battery {
     pub fn get_live_percent(id: u32) -> Result<u32, BatteryError> {
-        let percent_file_string = format!("{POWER_SUPPLY_PATH}/BAT{id}/capacity");
-        let percent_file = Path::new(&percent_file_string);
+        let percent_file = percent_path(id);
 
         let string_from_file = match std::fs::read_to_string(percent_file) {
             Ok(out) => out,
@@ -69,8 +108,7 @@ impl Battery {
     }
 
     pub fn get_live_status(id: u32) -> Result<ChargeStatus, BatteryError> {
-        let status_file_string = format!("{POWER_SUPPLY_PATH}/BAT{id}/status");
-        let status_file = Path::new(&status_file_string);
+        let status_file = status_path(id);
 
         let string_from_file = match std::fs::read_to_string(status_file) {
             Ok(out) => out,
@@ -88,13 +126,113 @@ impl Battery {
     }
 }
 
+fn read_number(name: &str, attribute: &str) -> Option<f64> {
+    read_attribute(name, attribute).ok()?.parse().ok()
+}
+
+/// Current draw in watts, from power_now or current_now * voltage_now
+pub fn read_watts(id: u32) -> Option<f64> {
+    let name = format!("BAT{id}");
+
+    let microwatts = read_number(&name, "power_now").or_else(|| {
+        Some(read_number(&name, "current_now")? * read_number(&name, "voltage_now")? / 1e6)
+    })?;
+
+    Some(microwatts / 1e6)
+}
+
+/// Full capacity as a percentage of the design capacity
+pub fn read_health(id: u32) -> Option<u32> {
+    let name = format!("BAT{id}");
+
+    let (full, design) = match (
+        read_number(&name, "energy_full"),
+        read_number(&name, "energy_full_design"),
+    ) {
+        (Some(full), Some(design)) => (full, design),
+        _ => (
+            read_number(&name, "charge_full")?,
+            read_number(&name, "charge_full_design")?,
+        ),
+    };
+
+    (design > 0.0).then(|| (full * 100.0 / design).round() as u32)
+}
+
+pub const START_THRESHOLD: &str = "charge_control_start_threshold";
+pub const END_THRESHOLD: &str = "charge_control_end_threshold";
+pub const CHARGE_BEHAVIOUR: &str = "charge_behaviour";
+
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct ChargeThresholds {
+    pub start: Option<u32>,
+    pub end: Option<u32>,
+    /// Active charge_behaviour, e.g. `auto` or `inhibit-charge`
+    pub behaviour: Option<String>,
+    pub behaviours: Vec<String>,
+}
+
+pub fn read_thresholds(id: u32) -> ChargeThresholds {
+    let name = format!("BAT{id}");
+    let threshold = |attribute| read_attribute(&name, attribute).ok()?.parse().ok();
+
+    // "[auto] inhibit-charge force-discharge", the active one in brackets
+    let behaviours = read_attribute(&name, CHARGE_BEHAVIOUR).unwrap_or_default();
+
+    ChargeThresholds {
+        start: threshold(START_THRESHOLD),
+        end: threshold(END_THRESHOLD),
+        behaviour: behaviours
+            .split_whitespace()
+            .find(|mode| mode.starts_with('['))
+            .map(|mode| mode.trim_matches(['[', ']']).to_string()),
+        behaviours: behaviours
+            .split_whitespace()
+            .map(|mode| mode.trim_matches(['[', ']']).to_string())
+            .collect(),
+    }
+}
+
+pub fn write_attribute(name: &str, attribute: &str, value: &str) -> std::io::Result<()> {
+    std::fs::write(attribute_path(name, attribute), value)
+}
+
+/// Whether any adapter is online, `None` when there are no adapters
+pub fn adapter_online() -> Option<bool> {
+    let adapters: Vec<bool> = power_supplies()
+        .ok()?
+        .into_iter()
+        .filter(|supply| !supply.is_battery())
+        .filter_map(|supply| supply.online)
+        .collect();
+
+    (!adapters.is_empty()).then(|| adapters.contains(&true))
+}
+
+/// Wattage negotiated by an online USB-PD source, from its `ucsi-source-psy-*`
+/// voltage_max and current_max
+pub fn charger_watts() -> Option<f64> {
+    power_supplies()
+        .ok()?
+        .into_iter()
+        .filter(|supply| supply.name.starts_with("ucsi-source-psy-") && supply.online == Some(true))
+        .filter_map(|supply| {
+            Some(
+                read_number(&supply.name, "voltage_max")?
+                    * read_number(&supply.name, "current_max")?
+                    / 1e12,
+            )
+        })
+        .reduce(f64::max)
+}
+
 pub struct Batteries {
     pub entry: Vec<Battery>,
 }
 
 impl Default for Batteries {
     fn default() -> Self {
-        let dir_entries = match std::fs::read_dir(Path::new(POWER_SUPPLY_PATH)) {
+        let dir_entries = match std::fs::read_dir(power_supply_dir()) {
             Ok(out) => out,
             Err(e) => {
                 log::error!("{}", e);
@@ -157,10 +295,52 @@ impl Default for Batteries {
             })
             .collect();
 
-        entries.sort_by(|a, b| a.id.cmp(&b.id));
+        entries.sort_by_key(|a| a.id);
 
         log::debug!("default batteries data: {:?}", entries);
 
         Self { entry: entries }
     }
 }
+
+#[derive(Debug, Serialize)]
+pub struct PowerSupply {
+    pub name: String,
+    pub kind: String,
+    pub scope: Option<String>,
+    pub online: Option<bool>,
+    pub percent: Option<u32>,
+    pub status: Option<ChargeStatus>,
+}
+
+impl PowerSupply {
+    pub fn is_battery(&self) -> bool {
+        self.kind == "Battery"
+    }
+
+    fn read(name: String) -> Self {
+        let attr = |attribute| read_attribute(&name, attribute).ok();
+
+        PowerSupply {
+            kind: attr("type").unwrap_or("Unknown".to_string()),
+            scope: attr("scope"),
+            online: attr("online").map(|online| online == "1"),
+            percent: attr("capacity").and_then(|percent| percent.parse().ok()),
+            status: attr("status").map(|status| status.as_str().into()),
+            name,
+        }
+    }
+}
+
+pub fn power_supplies() -> std::io::Result<Vec<PowerSupply>> {
+    let mut supplies = std::fs::read_dir(power_supply_dir())?
+        .map(|entry| Ok(entry?.file_name().to_string_lossy().to_string()))
+        .collect::<std::io::Result<Vec<String>>>()?
+        .into_iter()
+        .map(PowerSupply::read)
+        .collect::<Vec<PowerSupply>>();
+
+    supplies.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(supplies)
+}
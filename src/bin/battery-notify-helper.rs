@@ -0,0 +1,93 @@
+//! Writes charge thresholds as root on behalf of `battery-notify thresholds`.
+//!
+//! Takes nothing but a battery number and one of
+//!
+//!     battery-notify-helper ID set START END
+//!     battery-notify-helper ID behaviour MODE
+//!
+//! and always writes below /sys/class/power_supply, so polkit can let users
+//! run it without handing them the whole daemon as root. To install:
+//!
+//!     install -Dm755 target/release/battery-notify-helper /usr/libexec/battery-notify-helper
+//!     install -Dm644 data/org.batterynotify.thresholds.policy \
+//!         /usr/share/polkit-1/actions/org.batterynotify.thresholds.policy
+
+use std::{path::PathBuf, process::exit};
+
+const POWER_SUPPLY_PATH: &str = "/sys/class/power_supply";
+
+const START_THRESHOLD: &str = "charge_control_start_threshold";
+const END_THRESHOLD: &str = "charge_control_end_threshold";
+const CHARGE_BEHAVIOUR: &str = "charge_behaviour";
+
+const USAGE: &str = "usage: battery-notify-helper ID set START END\n       \
+                     battery-notify-helper ID behaviour MODE";
+
+fn fail(message: &str) -> ! {
+    eprintln!("battery-notify-helper: {message}");
+    exit(1);
+}
+
+fn number(value: &str) -> u32 {
+    value
+        .parse()
+        .unwrap_or_else(|_| fail(&format!("invalid number '{value}'")))
+}
+
+fn path(id: u32, attribute: &str) -> PathBuf {
+    PathBuf::from(POWER_SUPPLY_PATH)
+        .join(format!("BAT{id}"))
+        .join(attribute)
+}
+
+fn write(id: u32, attribute: &str, value: &str) {
+    if let Err(e) = std::fs::write(path(id, attribute), value) {
+        fail(&format!("BAT{id}/{attribute}: {e}"));
+    }
+}
+
+fn set(id: u32, start: u32, end: u32) {
+    if end == 0 || end > 100 || start >= end {
+        fail(&format!("invalid thresholds {start}-{end}"));
+    }
+
+    let current_end = std::fs::read_to_string(path(id, END_THRESHOLD))
+        .ok()
+        .and_then(|value| value.trim().parse::<u32>().ok());
+
+    // keep start below end at every step, drivers reject anything else
+    if current_end.is_some_and(|current_end| start >= current_end) {
+        write(id, END_THRESHOLD, &end.to_string());
+        write(id, START_THRESHOLD, &start.to_string());
+    } else {
+        write(id, START_THRESHOLD, &start.to_string());
+        write(id, END_THRESHOLD, &end.to_string());
+    }
+}
+
+fn behaviour(id: u32, mode: &str) {
+    let supported = std::fs::read_to_string(path(id, CHARGE_BEHAVIOUR))
+        .unwrap_or_else(|e| fail(&format!("BAT{id}/{CHARGE_BEHAVIOUR}: {e}")));
+
+    if !supported
+        .split_whitespace()
+        .any(|supported| supported.trim_matches(['[', ']']) == mode)
+    {
+        fail(&format!(
+            "BAT{id} does not support charge behaviour '{mode}'"
+        ));
+    }
+
+    write(id, CHARGE_BEHAVIOUR, mode);
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    match args[..] {
+        [id, "set", start, end] => set(number(id), number(start), number(end)),
+        [id, "behaviour", mode] => behaviour(number(id), mode),
+        _ => fail(USAGE),
+    }
+}
@@ -0,0 +1,120 @@
+use std::{
+    cell::{Cell, RefCell},
+    future::Future,
+    pin::Pin,
+    time::Duration,
+};
+use tokio::sync::oneshot;
+
+type Time = chrono::DateTime<chrono::Local>;
+
+pub type Sleep = Pin<Box<dyn Future<Output = ()>>>;
+
+/// Times a sleeper is woken by a manual clock get to act before it moves on
+const SETTLE_YIELDS: usize = 16;
+
+pub trait Clock {
+    fn now(&self) -> Time;
+
+    /// Waits for `duration` to pass on this clock
+    fn sleep(&self, duration: Duration) -> Sleep;
+}
+
+/// Wall clock time
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Time {
+        chrono::Local::now()
+    }
+
+    fn sleep(&self, duration: Duration) -> Sleep {
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
+
+/// Time that only moves when told to, waking the sleepers it passes
+pub struct ManualClock {
+    now: Cell<Time>,
+    sleepers: RefCell<Vec<(Time, oneshot::Sender<()>)>>,
+}
+
+impl ManualClock {
+    pub fn new(now: Time) -> Self {
+        ManualClock {
+            now: Cell::new(now),
+            sleepers: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// The earliest time a sleeper waits for
+    pub fn next_deadline(&self) -> Option<Time> {
+        self.sleepers
+            .borrow_mut()
+            .iter()
+            .filter(|(_, sender)| !sender.is_closed())
+            .map(|(deadline, _)| *deadline)
+            .min()
+    }
+
+    /// Moves to `time`, stopping at every deadline on the way so the woken
+    /// tasks see the time they waited for
+    pub async fn advance_to(&self, time: Time) {
+        // freshly spawned tasks get to start their sleeps first
+        settle().await;
+
+        while let Some(deadline) = self.next_deadline().filter(|deadline| *deadline <= time) {
+            self.now.set(self.now.get().max(deadline));
+
+            let due: Vec<_> = {
+                let mut sleepers = self.sleepers.borrow_mut();
+                let (due, pending) = sleepers
+                    .drain(..)
+                    .partition(|(deadline, _)| *deadline <= self.now.get());
+                *sleepers = pending;
+                due
+            };
+
+            for (_, sender) in due {
+                let _ = sender.send(());
+            }
+
+            settle().await;
+        }
+
+        self.now.set(self.now.get().max(time));
+    }
+}
+
+async fn settle() {
+    for _ in 0..SETTLE_YIELDS {
+        tokio::task::yield_now().await;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Time {
+        self.now.get()
+    }
+
+    fn sleep(&self, duration: Duration) -> Sleep {
+        if duration.is_zero() {
+            return Box::pin(std::future::ready(()));
+        }
+
+        let Some(deadline) = chrono::Duration::from_std(duration)
+            .ok()
+            .and_then(|duration| self.now.get().checked_add_signed(duration))
+        else {
+            return Box::pin(std::future::pending());
+        };
+
+        let (sender, receiver) = oneshot::channel();
+        self.sleepers.borrow_mut().push((deadline, sender));
+
+        Box::pin(async move {
+            // a dropped clock wakes everyone
+            let _ = receiver.await;
+        })
+    }
+}
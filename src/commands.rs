@@ -0,0 +1,283 @@
+use crate::{
+    battery,
+    config::Config,
+    helper,
+    hooks::{self, Hooks},
+    icons,
+    messages::{Messages, Values},
+    notifier::{self, Event},
+};
+use serde::Serialize;
+use std::process::exit;
+
+fn print_json<T: Serialize + ?Sized>(value: &T) {
+    match serde_json::to_string_pretty(value) {
+        Ok(out) => println!("{out}"),
+        Err(e) => {
+            log::error!("json output error: {e}");
+            exit(1);
+        }
+    }
+}
+
+fn power_supplies() -> Vec<battery::PowerSupply> {
+    match battery::power_supplies() {
+        Ok(out) => out,
+        Err(e) => {
+            log::error!("cannot read power supplies: {e}");
+            exit(1);
+        }
+    }
+}
+
+pub fn status(json: bool) {
+    let supplies = power_supplies();
+
+    if json {
+        print_json(&supplies);
+        return;
+    }
+
+    for supply in supplies {
+        if supply.is_battery() {
+            println!(
+                "{:<12} {:>4} {:?}",
+                supply.name,
+                supply
+                    .percent
+                    .map(|p| format!("{p}%"))
+                    .unwrap_or("?".to_string()),
+                supply.status.unwrap_or(battery::ChargeStatus::Unknown)
+            );
+        } else {
+            println!(
+                "{:<12} {}",
+                supply.name,
+                match supply.online {
+                    Some(true) => "online",
+                    Some(false) => "offline",
+                    None => "?",
+                }
+            );
+        }
+    }
+}
+
+pub fn list(json: bool) {
+    let supplies = power_supplies();
+
+    if json {
+        #[derive(Serialize)]
+        struct Entry<'a> {
+            name: &'a str,
+            kind: &'a str,
+            scope: Option<&'a str>,
+        }
+
+        let entries: Vec<Entry> = supplies
+            .iter()
+            .map(|supply| Entry {
+                name: &supply.name,
+                kind: &supply.kind,
+                scope: supply.scope.as_deref(),
+            })
+            .collect();
+
+        print_json(&entries);
+        return;
+    }
+
+    for supply in supplies {
+        println!(
+            "{:<12} {:<10} {}",
+            supply.name,
+            supply.kind,
+            supply.scope.as_deref().unwrap_or("-")
+        );
+    }
+}
+
+pub fn hooks(config: &Config, json: bool) {
+    let hooks = Hooks::new(&config.hooks).list();
+
+    if json {
+        print_json(&hooks);
+        return;
+    }
+
+    if !config.hooks.enabled {
+        println!("hooks are disabled");
+    }
+
+    if hooks.is_empty() {
+        match hooks::scripts_dir() {
+            Some(dir) => println!("no hooks, scripts go to {}/<event>/", dir.display()),
+            None => println!("no hooks"),
+        }
+        return;
+    }
+
+    for entry in hooks {
+        println!("{}", entry.event);
+
+        for command in entry.commands {
+            println!("  command  {}", command.join(" "));
+        }
+
+        for script in entry.scripts {
+            println!("  script   {}", script.display());
+        }
+    }
+}
+
+pub async fn test(event: Event, config: &Config, json: bool) {
+    let percent = battery::Battery::get_live_percent(config.battery_id).unwrap_or(100);
+    let values = Values {
+        battery: config.battery_id,
+        percent,
+        status: battery::Battery::get_live_status(config.battery_id)
+            .unwrap_or(battery::ChargeStatus::Unknown),
+        eta: None,
+        watts: battery::read_watts(config.battery_id),
+        charger_watts: battery::charger_watts(),
+        health: battery::read_health(config.battery_id),
+        limit: battery::read_thresholds(config.battery_id).end,
+        start_percent: percent,
+        duration: chrono::Duration::zero(),
+    };
+    let body = Messages::load(&config.messages).render(event, &values);
+
+    let mut notification = notifier::event_notification(event, &body);
+    icons::decorate(
+        &mut notification,
+        &config.icons,
+        config.low_battery_percent,
+        values.percent,
+        values.status,
+    );
+
+    let result = notifier::announce(event, &notification).await;
+
+    if json {
+        #[derive(Serialize)]
+        struct TestResult<'a> {
+            event: Event,
+            body: &'a str,
+            id: Option<u32>,
+            error: Option<String>,
+        }
+
+        print_json(&TestResult {
+            event,
+            body: &body,
+            id: result.as_ref().ok().map(|handle| handle.id()),
+            error: result.as_ref().err().map(|e| e.to_string()),
+        });
+    } else if let Ok(handle) = &result {
+        println!("sent {:?} notification (id {})", event, handle.id());
+    }
+
+    if result.is_err() {
+        exit(1);
+    }
+}
+
+#[derive(Serialize)]
+struct Check {
+    name: &'static str,
+    ok: bool,
+    detail: String,
+}
+
+impl Check {
+    fn new(name: &'static str, result: Result<String, String>) -> Self {
+        let (ok, detail) = match result {
+            Ok(detail) => (true, detail),
+            Err(detail) => (false, detail),
+        };
+
+        Check { name, ok, detail }
+    }
+}
+
+fn check_sysfs(config: &Config) -> Result<String, String> {
+    let supplies = battery::power_supplies().map_err(|e| e.to_string())?;
+    let name = format!("BAT{}", config.battery_id);
+
+    battery::read_attribute(&name, "capacity").map_err(|e| format!("{name}/capacity: {e}"))?;
+    battery::read_attribute(&name, "status").map_err(|e| format!("{name}/status: {e}"))?;
+
+    Ok(format!(
+        "{} power supplies, {name} readable",
+        supplies.len()
+    ))
+}
+
+fn check_notification_server() -> Result<String, String> {
+    let info = notify_rust::get_server_information().map_err(|e| e.to_string())?;
+    Ok(format!("{} {} ({})", info.name, info.version, info.vendor))
+}
+
+fn check_audio() -> Result<String, String> {
+    rodio::OutputStream::try_default()
+        .map(|_| "default output device available".to_string())
+        .map_err(|e| e.to_string())
+}
+
+fn check_config(config_path: Option<&std::path::Path>) -> Result<String, String> {
+    let path = config_path
+        .map(|p| p.to_path_buf())
+        .or_else(Config::default_path);
+
+    Config::load(config_path).map_err(|e| e.to_string())?;
+
+    Ok(match path {
+        Some(p) if p.exists() => format!("{} is valid", p.display()),
+        _ => "no config file, using defaults".to_string(),
+    })
+}
+
+fn check_log_dir() -> Result<String, String> {
+    let dir = helper::log_dir().ok_or("neither XDG_CACHE_HOME nor HOME is set")?;
+
+    std::fs::create_dir_all(&dir).map_err(|e| format!("{}: {e}", dir.display()))?;
+
+    let metadata = std::fs::metadata(&dir).map_err(|e| format!("{}: {e}", dir.display()))?;
+
+    if !metadata.is_dir() {
+        return Err(format!("{} is not a directory", dir.display()));
+    }
+
+    if metadata.permissions().readonly() {
+        return Err(format!("{} is read-only", dir.display()));
+    }
+
+    Ok(format!("{} is writable", dir.display()))
+}
+
+pub fn doctor(config: &Config, config_path: Option<&std::path::Path>, json: bool) {
+    let checks = [
+        Check::new("sysfs", check_sysfs(config)),
+        Check::new("notifications", check_notification_server()),
+        Check::new("audio", check_audio()),
+        Check::new("config", check_config(config_path)),
+        Check::new("log-dir", check_log_dir()),
+    ];
+
+    if json {
+        print_json(&checks[..]);
+    } else {
+        for check in &checks {
+            println!(
+                "[{}] {:<14} {}",
+                if check.ok { " ok " } else { "fail" },
+                check.name,
+                check.detail
+            );
+        }
+    }
+
+    if checks.iter().any(|check| !check.ok) {
+        exit(1);
+    }
+}
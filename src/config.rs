@@ -0,0 +1,256 @@
+use crate::{
+    actions::ActionsConfig,
+    bar::BarConfig,
+    debounce::DebounceConfig,
+    helper,
+    hooks::HooksConfig,
+    icons::IconsConfig,
+    logging::{LogConfig, LogFormat, LogLevel},
+    messages::MessagesConfig,
+    power_profile::PowerProfileConfig,
+    quiet::QuietConfig,
+    reminder::ReminderConfig,
+    thresholds::ThresholdsConfig,
+    UserArgs,
+};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub dev: bool,
+    pub battery_id: u32,
+    pub low_battery_percent: u32,
+    pub actions: ActionsConfig,
+    pub messages: MessagesConfig,
+    pub icons: IconsConfig,
+    pub quiet: QuietConfig,
+    pub reminders: ReminderConfig,
+    pub debounce: DebounceConfig,
+    pub thresholds: ThresholdsConfig,
+    pub hooks: HooksConfig,
+    pub power_profile: PowerProfileConfig,
+    pub bar: BarConfig,
+    pub log: LogConfig,
+    #[serde(skip)]
+    file_keys: FileKeys,
+}
+
+/// Settings the config file gives itself, which `--dev` leaves alone
+#[derive(Debug, Clone, Default)]
+struct FileKeys {
+    low_battery_percent: bool,
+    log_format: bool,
+    log_file_name: bool,
+}
+
+impl FileKeys {
+    fn read(table: &toml::Table) -> Self {
+        let log = table.get("log").and_then(toml::Value::as_table);
+
+        FileKeys {
+            low_battery_percent: table.contains_key("low_battery_percent"),
+            log_format: log.is_some_and(|log| log.contains_key("format")),
+            log_file_name: log.is_some_and(|log| log.contains_key("file_name")),
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            dev: false,
+            battery_id: 0,
+            low_battery_percent: 20,
+            actions: ActionsConfig::default(),
+            messages: MessagesConfig::default(),
+            icons: IconsConfig::default(),
+            quiet: QuietConfig::default(),
+            reminders: ReminderConfig::default(),
+            debounce: DebounceConfig::default(),
+            thresholds: ThresholdsConfig::default(),
+            hooks: HooksConfig::default(),
+            power_profile: PowerProfileConfig::default(),
+            bar: BarConfig::default(),
+            log: LogConfig::default(),
+            file_keys: FileKeys::default(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, std::io::Error),
+    Parse(PathBuf, toml::de::Error),
+    Invalid(String),
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::Io(path, e) => write!(f, "{}: {e}", path.display()),
+            ConfigError::Parse(path, e) => write!(f, "{}: {e}", path.display()),
+            ConfigError::Invalid(reason) => write!(f, "{reason}"),
+        }
+    }
+}
+
+impl Config {
+    pub fn default_path() -> Option<PathBuf> {
+        Some(helper::config_dir()?.join("config.toml"))
+    }
+
+    pub fn load(path: Option<&Path>) -> Result<Self, ConfigError> {
+        let (path, required) = match path {
+            Some(p) => (p.to_path_buf(), true),
+            None => match Self::default_path() {
+                Some(p) => (p, false),
+                None => return Ok(Config::default()),
+            },
+        };
+
+        let text = match std::fs::read_to_string(&path) {
+            Ok(out) => out,
+            Err(e) if !required && e.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(Config::default())
+            }
+            Err(e) => return Err(ConfigError::Io(path, e)),
+        };
+
+        let mut config: Config =
+            toml::from_str(&text).map_err(|e| ConfigError::Parse(path.clone(), e))?;
+        let table: toml::Table = toml::from_str(&text).map_err(|e| ConfigError::Parse(path, e))?;
+        config.file_keys = FileKeys::read(&table);
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    /// The config file with the command line on top, validated as a whole
+    pub fn load_with_args(args: &UserArgs) -> Result<Self, ConfigError> {
+        let config = Self::load(args.config_path.as_deref())?.with_args(args);
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.low_battery_percent > 100 {
+            return Err(ConfigError::Invalid(format!(
+                "low_battery_percent must be within 0-100, got {}",
+                self.low_battery_percent
+            )));
+        }
+
+        self.quiet
+            .validate()
+            .map_err(|e| ConfigError::Invalid(format!("quiet: {e}")))?;
+
+        self.hooks
+            .validate()
+            .map_err(|e| ConfigError::Invalid(format!("hooks: {e}")))?;
+
+        Ok(())
+    }
+
+    pub fn with_args(mut self, args: &UserArgs) -> Self {
+        if let Some(id) = args.battery_id {
+            self.battery_id = id;
+        }
+
+        if let Some(percent) = args.low_battery_percent {
+            self.low_battery_percent = percent;
+        }
+
+        if let Some(sinks) = &args.log_sinks {
+            self.log.sinks = sinks.clone();
+        }
+
+        if let Some(format) = args.log_format {
+            self.log.format = format;
+        }
+
+        if let Some(level) = LogLevel::from_env() {
+            self.log.level = Some(level);
+        }
+
+        if let Some(level) = args.log_level {
+            self.log.level = Some(level);
+        }
+
+        self.dev |= args.dev;
+
+        if self.dev {
+            if args.low_battery_percent.is_none() && !self.file_keys.low_battery_percent {
+                self.low_battery_percent = 100;
+            }
+
+            if args.log_format.is_none() && !self.file_keys.log_format {
+                self.log.format = LogFormat::Verbose;
+            }
+
+            self.log.level.get_or_insert(LogLevel::Trace);
+
+            if !self.file_keys.log_file_name {
+                self.log.file_name = "debug-logs.txt".to_string();
+            }
+        }
+
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    fn args(argv: &[&str]) -> UserArgs {
+        crate::Cli::parse_from(std::iter::once("battery-notify").chain(argv.iter().copied())).args
+    }
+
+    fn write_config(name: &str, text: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "battery-notify-test-{}-{name}.toml",
+            std::process::id()
+        ));
+        std::fs::write(&path, text).unwrap();
+        path
+    }
+
+    #[test]
+    fn dev_fills_in_unset_values() {
+        let config = Config::default().with_args(&args(&["--dev"]));
+
+        assert_eq!(config.low_battery_percent, 100);
+        assert_eq!(config.log.file_name, "debug-logs.txt");
+        assert_eq!(config.log.format, LogFormat::Verbose);
+    }
+
+    #[test]
+    fn dev_keeps_values_from_the_file() {
+        let path = write_config(
+            "dev",
+            "low_battery_percent = 15\n[log]\nfile_name = \"mine.txt\"\nformat = \"json\"\n",
+        );
+        let config = Config::load(Some(&path))
+            .unwrap()
+            .with_args(&args(&["--dev"]));
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(config.low_battery_percent, 15);
+        assert_eq!(config.log.file_name, "mine.txt");
+        assert_eq!(config.log.format, LogFormat::Json);
+    }
+
+    #[test]
+    fn command_line_is_validated() {
+        let path = write_config("low", "");
+        let path_arg = path.to_string_lossy().to_string();
+        let res = Config::load_with_args(&args(&["-c", &path_arg, "-l", "150"]));
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(matches!(res, Err(ConfigError::Invalid(_))));
+    }
+}
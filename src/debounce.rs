@@ -0,0 +1,131 @@
+use serde::{Deserialize, Serialize};
+use std::{collections::VecDeque, time::Duration};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DebounceConfig {
+    /// A status has to hold this long before it is announced
+    pub settle_ms: u64,
+    /// Least time between two announced status changes
+    pub min_dwell_secs: u64,
+    /// Status changes within `flap_window_secs` that count as flapping
+    pub flap_threshold: usize,
+    pub flap_window_secs: u64,
+    /// Send a "check your charger" notification when flapping
+    pub warn_on_flap: bool,
+}
+
+impl Default for DebounceConfig {
+    fn default() -> Self {
+        DebounceConfig {
+            settle_ms: 3000,
+            min_dwell_secs: 5,
+            flap_threshold: 4,
+            flap_window_secs: 60,
+            warn_on_flap: true,
+        }
+    }
+}
+
+impl DebounceConfig {
+    pub fn settle(&self) -> Duration {
+        Duration::from_millis(self.settle_ms)
+    }
+
+    pub fn min_dwell(&self) -> chrono::Duration {
+        chrono::Duration::seconds(self.min_dwell_secs as i64)
+    }
+
+    /// Longest a status change can take to be announced
+    pub fn max_delay(&self) -> Duration {
+        self.settle() + Duration::from_secs(self.min_dwell_secs)
+    }
+
+    fn flap_window(&self) -> chrono::Duration {
+        chrono::Duration::seconds(self.flap_window_secs as i64)
+    }
+}
+
+/// Raw status changes within the flap window
+#[derive(Default)]
+pub struct FlapTracker {
+    changes: VecDeque<chrono::DateTime<chrono::Local>>,
+    warned: bool,
+    pub flaps: u32,
+}
+
+impl FlapTracker {
+    /// Records a raw change, true once per burst when it starts flapping
+    pub fn record(
+        &mut self,
+        config: &DebounceConfig,
+        now: chrono::DateTime<chrono::Local>,
+    ) -> bool {
+        while matches!(self.changes.front(), Some(time) if now - *time > config.flap_window()) {
+            self.changes.pop_front();
+        }
+
+        if self.changes.is_empty() {
+            self.warned = false;
+        }
+
+        self.changes.push_back(now);
+
+        if config.flap_threshold == 0 || self.changes.len() < config.flap_threshold {
+            return false;
+        }
+
+        self.flaps += 1;
+
+        !std::mem::replace(&mut self.warned, true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record_at(
+        tracker: &mut FlapTracker,
+        start: chrono::DateTime<chrono::Local>,
+        secs: &[i64],
+    ) -> Vec<bool> {
+        let config = DebounceConfig::default();
+
+        secs.iter()
+            .map(|secs| tracker.record(&config, start + chrono::Duration::seconds(*secs)))
+            .collect()
+    }
+
+    #[test]
+    fn warns_once_per_burst() {
+        let start = chrono::Local::now();
+        let mut tracker = FlapTracker::default();
+
+        assert_eq!(
+            record_at(&mut tracker, start, &[0, 5, 10, 15, 20, 25]),
+            [false, false, false, true, false, false]
+        );
+        assert_eq!(tracker.flaps, 3);
+
+        // a quiet spell longer than the window ends the burst
+        assert_eq!(
+            record_at(&mut tracker, start, &[200, 201, 202, 203]),
+            [false, false, false, true]
+        );
+        assert_eq!(tracker.flaps, 4);
+    }
+
+    #[test]
+    fn spread_out_changes_do_not_flap() {
+        let start = chrono::Local::now();
+        let mut tracker = FlapTracker::default();
+
+        // never more than three changes within 60s
+        assert_eq!(
+            record_at(&mut tracker, start, &[0, 20, 40, 61, 81, 102]),
+            [false; 6]
+        );
+        assert_eq!(tracker.flaps, 0);
+    }
+}
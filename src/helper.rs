@@ -1,8 +1,12 @@
 use notify::{Config, Event, PollWatcher, RecursiveMode, Watcher};
-use std::path::Path;
-use std::process::exit;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+use std::path::{Path, PathBuf};
 use std::time::Duration;
-use tokio::sync::mpsc;
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    sync::mpsc,
+};
+use tokio_util::sync::CancellationToken;
 
 pub fn file_watcher() -> notify::Result<(PollWatcher, mpsc::Receiver<notify::Result<Event>>)> {
     let (tx, rx) = mpsc::channel(10);
@@ -13,7 +17,8 @@ pub fn file_watcher() -> notify::Result<(PollWatcher, mpsc::Receiver<notify::Res
                 .build()
                 .unwrap()
                 .block_on(async {
-                    tx.send(res).await.unwrap();
+                    // the receiver is gone once the watchers shut down
+                    let _ = tx.send(res).await;
                 });
         },
         Config::default()
@@ -24,6 +29,80 @@ pub fn file_watcher() -> notify::Result<(PollWatcher, mpsc::Receiver<notify::Res
     Ok((watcher, rx))
 }
 
+pub fn watch_file<P: AsRef<Path>>(
+    path: P,
+) -> notify::Result<(PollWatcher, mpsc::Receiver<notify::Result<Event>>)> {
+    let (mut watcher, rx) = file_watcher()?;
+    watcher.watch(path.as_ref(), RecursiveMode::NonRecursive)?;
+
+    Ok((watcher, rx))
+}
+
+pub fn watch_dir<P: AsRef<Path>>(
+    path: P,
+) -> notify::Result<(PollWatcher, mpsc::Receiver<notify::Result<Event>>)> {
+    let (mut watcher, rx) = file_watcher()?;
+    watcher.watch(path.as_ref(), RecursiveMode::Recursive)?;
+
+    Ok((watcher, rx))
+}
+
+/// `HH:MM:SS`, prefixed with days past 24h and `-` when negative
+pub fn format_duration(duration: chrono::Duration) -> String {
+    let sign = if duration < chrono::Duration::zero() {
+        "-"
+    } else {
+        ""
+    };
+    let total = duration.num_seconds().unsigned_abs();
+
+    let (days, hours) = (total / 86400, (total / 3600) % 24);
+    let (minutes, seconds) = ((total / 60) % 60, total % 60);
+
+    if days > 0 {
+        format!("{sign}{days}d {hours:02}:{minutes:02}:{seconds:02}")
+    } else {
+        format!("{sign}{hours:02}:{minutes:02}:{seconds:02}")
+    }
+}
+
+pub fn render_template(template: &str, values: &[(&str, String)]) -> String {
+    values
+        .iter()
+        .fold(template.to_string(), |text, (key, value)| {
+            text.replace(&format!("{{{key}}}"), value)
+        })
+}
+
+pub async fn shutdown_signal(token: &CancellationToken) -> i32 {
+    let (mut sigterm, mut sigint) = match (
+        signal(SignalKind::terminate()),
+        signal(SignalKind::interrupt()),
+    ) {
+        (Ok(term), Ok(int)) => (term, int),
+        (Err(e), _) | (_, Err(e)) => {
+            log::error!("cannot install signal handlers: {e}");
+            token.cancel();
+            return 1;
+        }
+    };
+
+    let exit_code = tokio::select! {
+        _ = sigterm.recv() => {
+            log::info!("SIGTERM received, shutting down");
+            0
+        }
+        _ = sigint.recv() => {
+            log::info!("SIGINT received, shutting down");
+            130
+        }
+        _ = token.cancelled() => 1,
+    };
+
+    token.cancel();
+    exit_code
+}
+
 pub fn prog_name() -> Option<String> {
     Some(
         std::env::current_exe()
@@ -34,11 +113,72 @@ pub fn prog_name() -> Option<String> {
     )
 }
 
+pub fn xdg_config_home() -> Option<PathBuf> {
+    match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => Some(PathBuf::from(dir)),
+        _ => Some(PathBuf::from(std::env::var_os("HOME")?).join(".config")),
+    }
+}
+
+pub fn config_dir() -> Option<PathBuf> {
+    Some(xdg_config_home()?.join("battery-notify"))
+}
+
+fn has_xdg_runtime_dir() -> bool {
+    std::env::var_os("XDG_RUNTIME_DIR").is_some_and(|dir| !dir.is_empty())
+}
+
+/// `$XDG_RUNTIME_DIR`, or a per-user directory in the temp dir since a fixed
+/// name there could be taken by another user first
+pub fn runtime_dir() -> PathBuf {
+    match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => std::env::temp_dir().join(format!("battery-notify-{}", unsafe { libc::getuid() })),
+    }
+}
+
+/// Creates the fallback runtime dir with mode 0700, refusing one that belongs
+/// to another user or that others can get into
+pub fn ensure_runtime_dir() -> std::io::Result<PathBuf> {
+    let dir = runtime_dir();
+
+    if has_xdg_runtime_dir() {
+        return Ok(dir);
+    }
+
+    match std::fs::DirBuilder::new().mode(0o700).create(&dir) {
+        Ok(_) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {}
+        Err(e) => return Err(e),
+    }
+
+    let metadata = std::fs::symlink_metadata(&dir)?;
+
+    if !metadata.is_dir()
+        || metadata.uid() != unsafe { libc::getuid() }
+        || metadata.mode() & 0o077 != 0
+    {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::PermissionDenied,
+            "not a directory private to this user",
+        ));
+    }
+
+    Ok(dir)
+}
+
+pub fn log_dir() -> Option<PathBuf> {
+    let base = match std::env::var_os("XDG_CACHE_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".cache"),
+    };
+
+    Some(base.join("battery-notify"))
+}
+
 #[allow(dead_code)]
 pub async fn async_watch<P: AsRef<Path>>(path: P) -> notify::Result<()> {
-    let (mut watcher, mut rx) = file_watcher()?;
-
-    watcher.watch(path.as_ref(), RecursiveMode::NonRecursive)?;
+    let (_watcher, mut rx) = watch_file(path)?;
 
     while let Some(res) = rx.recv().await {
         match res {
@@ -50,88 +190,21 @@ pub async fn async_watch<P: AsRef<Path>>(path: P) -> notify::Result<()> {
     Ok(())
 }
 
-pub fn setup_logging() {
-    #[cfg(not(debug_assertions))]
-    let mut log_level = log::LevelFilter::Info;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    #[cfg(debug_assertions)]
-    let mut log_level = log::LevelFilter::Trace;
-
-    if let Ok(txt) = std::env::var("LOGGING") {
-        let level_txt = txt.to_lowercase();
-
-        match level_txt.as_str() {
-            "error" => log_level = log::LevelFilter::Error,
-            "warn" => log_level = log::LevelFilter::Warn,
-            "info" => log_level = log::LevelFilter::Info,
-            "debug" => log_level = log::LevelFilter::Debug,
-            "trace" => log_level = log::LevelFilter::Trace,
-            _ => {}
-        }
+    #[test]
+    fn formats_durations() {
+        assert_eq!(format_duration(chrono::Duration::seconds(3725)), "01:02:05");
+        assert_eq!(format_duration(chrono::Duration::seconds(-65)), "-00:01:05");
+        assert_eq!(
+            format_duration(chrono::Duration::seconds(2 * 86400 + 3600 + 1)),
+            "2d 01:00:01"
+        );
+        assert_eq!(
+            format_duration(chrono::Duration::seconds(-(86400 + 59))),
+            "-1d 00:00:59"
+        );
     }
-
-    #[cfg(not(debug_assertions))]
-    fern::Dispatch::new()
-        .format(|out, message, record| {
-            out.finish(format_args!(
-                "[{} {}] ({}): {}",
-                chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
-                record.level(),
-                prog_name().unwrap_or("saltyfishie".to_string()),
-                message
-            ))
-        })
-        .level(log_level)
-        .level_for("notify", log::LevelFilter::Info)
-        .level_for("mio", log::LevelFilter::Info)
-        .level_for("polling", log::LevelFilter::Info)
-        .level_for("async_io", log::LevelFilter::Info)
-        .chain(
-            match fern::log_file(format!(
-                "{}/.cache/battery-notify/logs.txt",
-                std::env::var("HOME").unwrap()
-            )) {
-                Ok(out) => out,
-                Err(e) => {
-                    eprintln!("{e}");
-                    exit(1);
-                }
-            },
-        )
-        .chain(std::io::stdout())
-        .apply()
-        .unwrap();
-
-    #[cfg(debug_assertions)]
-    fern::Dispatch::new()
-        .format(|out, message, record| {
-            out.finish(format_args!(
-                "[{} {}] {}::{}\n- {}\n",
-                chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
-                record.level(),
-                record.file().unwrap(),
-                record.line().unwrap_or_default(),
-                message
-            ))
-        })
-        .level(log_level)
-        .level_for("notify", log::LevelFilter::Info)
-        .level_for("mio", log::LevelFilter::Info)
-        .level_for("polling", log::LevelFilter::Info)
-        .level_for("async_io", log::LevelFilter::Info)
-        .chain(
-            match fern::log_file(format!(
-                "{}/.cache/battery-notify/debug-logs.txt",
-                std::env::var("HOME").unwrap()
-            )) {
-                Ok(out) => out,
-                Err(e) => {
-                    eprintln!("{e}");
-                    exit(1);
-                }
-            },
-        )
-        .chain(std::io::stdout())
-        .apply()
-        .unwrap();
 }
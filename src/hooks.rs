@@ -0,0 +1,542 @@
+use crate::{battery::ChargeStatus, helper};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    io::Write,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    process::{ExitStatus, Stdio},
+    rc::Rc,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+use tokio::sync::Semaphore;
+
+const WAIT_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone, clap::Subcommand)]
+pub enum HooksCommand {
+    /// Show the commands and scripts that run for each event
+    List {
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum HookEvent {
+    Plugged,
+    Unplugged,
+    /// The charge crossed this percentage, in either direction
+    Level(u32),
+    Full,
+    Critical,
+    HealthWarning,
+}
+
+impl fmt::Display for HookEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HookEvent::Plugged => write!(f, "plugged"),
+            HookEvent::Unplugged => write!(f, "unplugged"),
+            HookEvent::Level(percent) => write!(f, "level:{percent}"),
+            HookEvent::Full => write!(f, "full"),
+            HookEvent::Critical => write!(f, "critical"),
+            HookEvent::HealthWarning => write!(f, "health-warning"),
+        }
+    }
+}
+
+impl FromStr for HookEvent {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "plugged" => Ok(HookEvent::Plugged),
+            "unplugged" => Ok(HookEvent::Unplugged),
+            "full" => Ok(HookEvent::Full),
+            "critical" => Ok(HookEvent::Critical),
+            "health-warning" => Ok(HookEvent::HealthWarning),
+            _ => match value.strip_prefix("level:").map(str::parse::<u32>) {
+                Some(Ok(percent)) if percent <= 100 => Ok(HookEvent::Level(percent)),
+                _ => Err(format!("invalid hook event '{value}'")),
+            },
+        }
+    }
+}
+
+impl TryFrom<String> for HookEvent {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<HookEvent> for String {
+    fn from(value: HookEvent) -> Self {
+        value.to_string()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Hook {
+    pub on: HookEvent,
+    pub run: Vec<String>,
+    /// Overrides the `timeout_secs` of the hooks section
+    pub timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HooksConfig {
+    pub enabled: bool,
+    /// Hooks still running after this long are killed
+    pub timeout_secs: u64,
+    /// Most hooks running at once, the rest wait their turn
+    pub max_concurrent: usize,
+    /// Health in percent of the design capacity below which
+    /// `health-warning` fires
+    pub health_warning_percent: u32,
+    pub commands: Vec<Hook>,
+}
+
+impl Default for HooksConfig {
+    fn default() -> Self {
+        HooksConfig {
+            enabled: true,
+            timeout_secs: 30,
+            max_concurrent: 4,
+            health_warning_percent: 80,
+            commands: Vec::new(),
+        }
+    }
+}
+
+impl HooksConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        match self.commands.iter().find(|hook| hook.run.is_empty()) {
+            Some(hook) => Err(format!("empty command for '{}'", hook.on)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// What a hook gets to know about the event
+#[derive(Debug, Clone, Serialize)]
+pub struct HookData {
+    pub event: HookEvent,
+    pub time: chrono::DateTime<chrono::Local>,
+    pub battery: u32,
+    pub percent: u32,
+    pub status: ChargeStatus,
+    pub online: Option<bool>,
+    pub health: Option<u32>,
+}
+
+impl HookData {
+    fn env(&self) -> Vec<(&'static str, String)> {
+        let mut env = vec![
+            ("BATTERY_EVENT", self.event.to_string()),
+            ("BATTERY_TIME", self.time.to_rfc3339()),
+            ("BATTERY_ID", self.battery.to_string()),
+            ("BATTERY_PERCENT", self.percent.to_string()),
+            ("BATTERY_STATUS", self.status.to_string()),
+        ];
+
+        if let Some(online) = self.online {
+            env.push(("BATTERY_ONLINE", if online { "1" } else { "0" }.to_string()));
+        }
+
+        if let Some(health) = self.health {
+            env.push(("BATTERY_HEALTH", health.to_string()));
+        }
+
+        env
+    }
+}
+
+/// `hooks.d` under the config dir, one subdirectory of scripts per event
+pub fn scripts_dir() -> Option<PathBuf> {
+    Some(helper::config_dir()?.join("hooks.d"))
+}
+
+fn is_executable(path: &Path) -> bool {
+    std::fs::metadata(path)
+        .is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+}
+
+/// Executable scripts per event, in lexical order. Hidden files and editor
+/// backups are left out like run-parts does.
+fn scan(dir: &Path) -> BTreeMap<HookEvent, Vec<PathBuf>> {
+    let mut scripts = BTreeMap::new();
+
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return scripts;
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+
+        if !path.is_dir() {
+            continue;
+        }
+
+        let name = entry.file_name().to_string_lossy().to_string();
+        let event = match name.parse::<HookEvent>() {
+            Ok(out) => out,
+            Err(e) => {
+                log::warn!("{}: {e}", path.display());
+                continue;
+            }
+        };
+
+        let Ok(files) = std::fs::read_dir(&path) else {
+            continue;
+        };
+
+        let mut found: Vec<PathBuf> = files
+            .flatten()
+            .map(|file| file.path())
+            .filter(|file| {
+                let name = file.file_name().unwrap_or_default().to_string_lossy();
+                !name.starts_with('.') && !name.ends_with('~')
+            })
+            .filter(|file| {
+                let executable = is_executable(file);
+                if !executable {
+                    log::warn!("{} is not executable, skipped", file.display());
+                }
+                executable
+            })
+            .collect();
+
+        found.sort();
+
+        if !found.is_empty() {
+            scripts.insert(event, found);
+        }
+    }
+
+    scripts
+}
+
+/// What runs for one event, as shown by `hooks list`
+#[derive(Debug, Serialize)]
+pub struct EventHooks {
+    pub event: HookEvent,
+    pub commands: Vec<Vec<String>>,
+    pub scripts: Vec<PathBuf>,
+}
+
+pub struct Hooks {
+    config: HooksConfig,
+    scripts: BTreeMap<HookEvent, Vec<PathBuf>>,
+    slots: Rc<Semaphore>,
+}
+
+impl Hooks {
+    pub fn new(config: &HooksConfig) -> Self {
+        let mut hooks = Hooks {
+            config: config.clone(),
+            scripts: BTreeMap::new(),
+            slots: Rc::new(Semaphore::new(config.max_concurrent.max(1))),
+        };
+
+        hooks.rescan();
+        hooks
+    }
+
+    pub fn rescan(&mut self) {
+        self.scripts = scripts_dir().map(|dir| scan(&dir)).unwrap_or_default();
+        log::debug!(
+            "{} hook scripts",
+            self.scripts.values().map(Vec::len).sum::<usize>()
+        );
+    }
+
+    pub fn health_warning_percent(&self) -> u32 {
+        self.config.health_warning_percent
+    }
+
+    fn events(&self) -> BTreeSet<HookEvent> {
+        self.config
+            .commands
+            .iter()
+            .map(|hook| hook.on)
+            .chain(self.scripts.keys().copied())
+            .collect()
+    }
+
+    /// Level events passed on the way from one percentage to the other.
+    /// Fires in both directions, so `level:80` works for "charged to 80%" as
+    /// well as "drained to 80%"; the script can tell them apart by status
+    pub fn crossed(&self, from: u32, to: u32) -> Vec<HookEvent> {
+        self.events()
+            .into_iter()
+            .filter(|event| match *event {
+                HookEvent::Level(level) => {
+                    (from > level && to <= level) || (from < level && to >= level)
+                }
+                _ => false,
+            })
+            .collect()
+    }
+
+    pub fn list(&self) -> Vec<EventHooks> {
+        self.events()
+            .into_iter()
+            .map(|event| EventHooks {
+                event,
+                commands: self
+                    .config
+                    .commands
+                    .iter()
+                    .filter(|hook| hook.on == event)
+                    .map(|hook| hook.run.clone())
+                    .collect(),
+                scripts: self.scripts.get(&event).cloned().unwrap_or_default(),
+            })
+            .collect()
+    }
+
+    /// Waits for the running and queued hooks
+    pub fn idle(&self) -> impl std::future::Future<Output = ()> {
+        let slots = self.slots.clone();
+        let count = self.config.max_concurrent.max(1) as u32;
+
+        async move {
+            // let freshly fired hooks take their slot first
+            tokio::task::yield_now().await;
+            let _ = slots.acquire_many(count).await;
+        }
+    }
+
+    /// Starts every command for the event without waiting for them. The
+    /// scripts of the event run one after another.
+    pub fn fire(&self, data: &HookData, dry_run: bool) {
+        if !self.config.enabled {
+            return;
+        }
+
+        let commands: Vec<&Hook> = self
+            .config
+            .commands
+            .iter()
+            .filter(|hook| hook.on == data.event)
+            .collect();
+        let scripts = self.scripts.get(&data.event).cloned().unwrap_or_default();
+
+        if dry_run {
+            let lines = commands
+                .iter()
+                .map(|hook| hook.run.join(" "))
+                .chain(scripts.iter().map(|script| script.display().to_string()));
+
+            for line in lines {
+                println!(
+                    "{} {:<7} {}: {line}",
+                    data.time.format("%Y-%m-%d %H:%M:%S"),
+                    "hook",
+                    data.event,
+                );
+            }
+            return;
+        }
+
+        let timeout = Duration::from_secs(self.config.timeout_secs);
+
+        for hook in commands {
+            let Some((program, args)) = hook.run.split_first() else {
+                continue;
+            };
+
+            tokio::task::spawn_local(run(
+                PathBuf::from(program),
+                args.to_vec(),
+                data.clone(),
+                hook.timeout_secs.map_or(timeout, Duration::from_secs),
+                self.slots.clone(),
+            ));
+        }
+
+        if scripts.is_empty() {
+            return;
+        }
+
+        let data = data.clone();
+        let slots = self.slots.clone();
+        tokio::task::spawn_local(async move {
+            for script in scripts {
+                run(script, Vec::new(), data.clone(), timeout, slots.clone()).await;
+            }
+        });
+    }
+}
+
+/// Waits for the hook on the calling thread, killing it at the deadline
+fn wait(
+    program: &Path,
+    args: &[String],
+    data: &HookData,
+    timeout: Duration,
+) -> std::io::Result<Option<ExitStatus>> {
+    let input = serde_json::to_string(data)?;
+
+    let mut child = std::process::Command::new(program)
+        .args(args)
+        .envs(data.env())
+        .stdin(Stdio::piped())
+        .spawn()?;
+
+    // a hook that does not read its input must not fail the others
+    if let Some(mut stdin) = child.stdin.take() {
+        if let Err(e) = writeln!(stdin, "{input}") {
+            log::debug!("hook {}: stdin: {e}", program.display());
+        }
+    }
+
+    let deadline = Instant::now() + timeout;
+
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+
+        if Instant::now() >= deadline {
+            child.kill()?;
+            child.wait()?;
+            return Ok(None);
+        }
+
+        std::thread::sleep(WAIT_INTERVAL);
+    }
+}
+
+async fn run(
+    program: PathBuf,
+    args: Vec<String>,
+    data: HookData,
+    timeout: Duration,
+    slots: Rc<Semaphore>,
+) {
+    let Ok(_permit) = slots.acquire().await else {
+        return;
+    };
+
+    let name = program.display().to_string();
+    log::debug!("running {} hook: {name} {}", data.event, args.join(" "));
+
+    // the wait runs off the event loop, slow hooks must not hold it up
+    let res = tokio::task::spawn_blocking(move || wait(&program, &args, &data, timeout)).await;
+
+    match res {
+        Ok(Ok(Some(status))) if status.success() => {
+            log::info!("hook {name} exited successfully")
+        }
+        Ok(Ok(Some(status))) => log::warn!("hook {name} failed: {status}"),
+        Ok(Ok(None)) => log::warn!(
+            "hook {name} timed out after {}s and was killed",
+            timeout.as_secs()
+        ),
+        Ok(Err(e)) => log::error!("cannot run hook {name}: {e}"),
+        Err(e) => log::error!("hook {name}: {e}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hooks(events: &[&str]) -> Hooks {
+        let config = HooksConfig {
+            commands: events
+                .iter()
+                .map(|event| Hook {
+                    on: event.parse().unwrap(),
+                    run: vec!["true".to_string()],
+                    timeout_secs: None,
+                })
+                .collect(),
+            ..HooksConfig::default()
+        };
+
+        // no scan, the machine's hooks.d must not leak in
+        Hooks {
+            slots: Rc::new(Semaphore::new(config.max_concurrent)),
+            config,
+            scripts: BTreeMap::new(),
+        }
+    }
+
+    fn data() -> HookData {
+        HookData {
+            event: HookEvent::Full,
+            time: chrono::Local::now(),
+            battery: 0,
+            percent: 100,
+            status: ChargeStatus::NotCharging,
+            online: Some(true),
+            health: None,
+        }
+    }
+
+    #[test]
+    fn parses_events() {
+        assert_eq!("plugged".parse(), Ok(HookEvent::Plugged));
+        assert_eq!("health-warning".parse(), Ok(HookEvent::HealthWarning));
+        assert_eq!("level:0".parse(), Ok(HookEvent::Level(0)));
+        assert_eq!("level:100".parse(), Ok(HookEvent::Level(100)));
+
+        for invalid in ["level:101", "level:", "level:-1", "Plugged", "charging"] {
+            assert!(invalid.parse::<HookEvent>().is_err(), "{invalid}");
+        }
+
+        assert_eq!(HookEvent::Level(20).to_string(), "level:20");
+    }
+
+    #[test]
+    fn levels_fire_in_both_directions() {
+        let hooks = hooks(&["level:20", "level:80", "full"]);
+
+        assert_eq!(hooks.crossed(21, 20), [HookEvent::Level(20)]);
+        assert_eq!(hooks.crossed(79, 80), [HookEvent::Level(80)]);
+        assert_eq!(
+            hooks.crossed(90, 10),
+            [HookEvent::Level(20), HookEvent::Level(80)]
+        );
+        // leaving a level is not crossing it
+        assert!(hooks.crossed(20, 21).is_empty());
+        assert!(hooks.crossed(80, 79).is_empty());
+        assert!(hooks.crossed(50, 50).is_empty());
+    }
+
+    #[test]
+    fn slow_hook_is_killed() {
+        let start = Instant::now();
+        let res = wait(
+            Path::new("sleep"),
+            &["10".to_string()],
+            &data(),
+            Duration::from_millis(300),
+        );
+
+        assert!(matches!(res, Ok(None)));
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn hook_exit_status_is_reported() {
+        let timeout = Duration::from_secs(5);
+
+        assert!(wait(Path::new("true"), &[], &data(), timeout)
+            .unwrap()
+            .is_some_and(|status| status.success()));
+        assert!(wait(Path::new("false"), &[], &data(), timeout)
+            .unwrap()
+            .is_some_and(|status| !status.success()));
+    }
+}
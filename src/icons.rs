@@ -0,0 +1,88 @@
+use crate::battery::ChargeStatus;
+use notify_rust::{Hint, Notification};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct IconsConfig {
+    pub enabled: bool,
+    /// Send the `value` hint so servers can draw a progress bar
+    pub progress: bool,
+    /// Icon names or image paths replacing a themed icon, keyed by its name
+    /// without the `battery-` prefix, e.g. `caution` or `good-charging`
+    pub custom: HashMap<String, String>,
+}
+
+impl Default for IconsConfig {
+    fn default() -> Self {
+        IconsConfig {
+            enabled: true,
+            progress: true,
+            custom: HashMap::new(),
+        }
+    }
+}
+
+/// Themed icon level, e.g. `caution`, `low-charging` or `full-charged`
+pub fn level(percent: u32, status: ChargeStatus, low_battery_percent: u32) -> String {
+    if status == ChargeStatus::Unknown {
+        return "missing".to_string();
+    }
+
+    if status == ChargeStatus::NotCharging && percent >= 95 {
+        return "full-charged".to_string();
+    }
+
+    let level = match percent {
+        0..=5 => "empty",
+        _ if percent <= low_battery_percent => "caution",
+        6..=40 => "low",
+        41..=80 => "good",
+        _ => "full",
+    };
+
+    if status == ChargeStatus::Charging {
+        format!("{level}-charging")
+    } else {
+        level.to_string()
+    }
+}
+
+pub fn decorate(
+    notification: &mut Notification,
+    config: &IconsConfig,
+    low_battery_percent: u32,
+    percent: u32,
+    status: ChargeStatus,
+) {
+    notification.hints.retain(|hint| {
+        !matches!(hint, Hint::CustomInt(name, _) if name == "value")
+            && !matches!(hint, Hint::ImagePath(_))
+    });
+
+    if config.progress {
+        notification.hint(Hint::CustomInt(
+            "value".to_string(),
+            percent.min(100) as i32,
+        ));
+    }
+
+    if !config.enabled {
+        return;
+    }
+
+    let level = level(percent, status, low_battery_percent);
+
+    match config.custom.get(&level) {
+        Some(image) if image.contains('/') => {
+            notification.icon("").image_path(image);
+        }
+        Some(icon) => {
+            notification.icon(icon);
+        }
+        None => {
+            notification.icon(&format!("battery-{level}"));
+        }
+    }
+}
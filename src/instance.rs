@@ -0,0 +1,111 @@
+use crate::helper;
+use std::{
+    fs::File,
+    io::{Read, Seek, Write},
+    os::fd::AsRawFd,
+    path::PathBuf,
+    time::Duration,
+};
+
+const REPLACE_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug)]
+pub enum InstanceError {
+    Io(PathBuf, std::io::Error),
+    AlreadyRunning(Option<i32>),
+    ReplaceTimeout(Option<i32>),
+}
+
+impl std::fmt::Display for InstanceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let pid = |pid: &Option<i32>| match pid {
+            Some(pid) => format!("pid {pid}"),
+            None => "unknown pid".to_string(),
+        };
+
+        match self {
+            InstanceError::Io(path, e) => write!(f, "{}: {e}", path.display()),
+            InstanceError::AlreadyRunning(p) => write!(
+                f,
+                "another instance is already running ({}), use `run --replace` to take over",
+                pid(p)
+            ),
+            InstanceError::ReplaceTimeout(p) => {
+                write!(f, "running instance ({}) did not exit in time", pid(p))
+            }
+        }
+    }
+}
+
+pub struct InstanceLock {
+    _file: File,
+}
+
+pub fn lock_path() -> PathBuf {
+    helper::runtime_dir().join("battery-notify.lock")
+}
+
+fn try_lock(file: &File) -> std::io::Result<bool> {
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0 {
+        return Ok(true);
+    }
+
+    let e = std::io::Error::last_os_error();
+    match e.raw_os_error() {
+        Some(libc::EWOULDBLOCK) => Ok(false),
+        _ => Err(e),
+    }
+}
+
+fn read_pid(file: &mut File) -> Option<i32> {
+    let mut text = String::new();
+    file.rewind().ok()?;
+    file.read_to_string(&mut text).ok()?;
+    text.trim().parse().ok()
+}
+
+pub fn acquire(replace: bool) -> Result<InstanceLock, InstanceError> {
+    helper::ensure_runtime_dir().map_err(|e| InstanceError::Io(helper::runtime_dir(), e))?;
+
+    let path = lock_path();
+    let io_err = |e| InstanceError::Io(path.clone(), e);
+
+    let mut file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&path)
+        .map_err(io_err)?;
+
+    if !try_lock(&file).map_err(io_err)? {
+        let pid = read_pid(&mut file);
+
+        if !replace {
+            return Err(InstanceError::AlreadyRunning(pid));
+        }
+
+        let Some(pid) = pid else {
+            return Err(InstanceError::ReplaceTimeout(None));
+        };
+
+        log::info!("replacing running instance (pid {pid})");
+        unsafe { libc::kill(pid, libc::SIGTERM) };
+
+        let start = std::time::Instant::now();
+        while !try_lock(&file).map_err(io_err)? {
+            if start.elapsed() > REPLACE_TIMEOUT {
+                return Err(InstanceError::ReplaceTimeout(Some(pid)));
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+    }
+
+    file.set_len(0).map_err(io_err)?;
+    file.rewind().map_err(io_err)?;
+    write!(file, "{}", std::process::id()).map_err(io_err)?;
+
+    log::debug!("acquired instance lock {}", path.display());
+
+    Ok(InstanceLock { _file: file })
+}
@@ -0,0 +1,254 @@
+use crate::{
+    config::{Config, ConfigError},
+    helper,
+    notifier::Notifier,
+};
+use serde::{Deserialize, Serialize};
+use std::{path::PathBuf, process::exit, rc::Rc};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWrite, AsyncWriteExt, BufReader},
+    net::{UnixListener, UnixStream},
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize, clap::Subcommand)]
+#[serde(tag = "cmd", rename_all = "lowercase")]
+pub enum Request {
+    /// Print the current daemon state
+    Get,
+
+    /// Silence the low battery alert for a while
+    Snooze {
+        #[arg(default_value_t = 10)]
+        minutes: u32,
+    },
+
+    /// Silence the low battery alert until the next charge
+    Dismiss,
+
+    /// Pause all notifications for a while
+    Pause { minutes: u32 },
+
+    /// Resume paused notifications
+    Resume,
+
+    /// Reload the config file
+    Reload,
+
+    /// Stream daemon events
+    Subscribe,
+}
+
+#[derive(Debug, Serialize)]
+struct Response<T: Serialize> {
+    ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    state: Option<T>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+pub fn socket_path() -> PathBuf {
+    helper::runtime_dir().join("battery-notify.sock")
+}
+
+async fn write_line<W, T>(stream: &mut W, value: &T) -> std::io::Result<()>
+where
+    W: AsyncWrite + Unpin,
+    T: Serialize,
+{
+    let mut line = serde_json::to_string(value)?;
+    line.push('\n');
+    stream.write_all(line.as_bytes()).await
+}
+
+fn reply<T: Serialize>(state: Option<T>, error: Option<String>) -> Response<T> {
+    Response {
+        ok: error.is_none(),
+        state,
+        error,
+    }
+}
+
+async fn handle_client<R>(
+    notifier: Rc<Notifier>,
+    reload: Rc<R>,
+    stream: UnixStream,
+) -> std::io::Result<()>
+where
+    R: Fn() -> Result<Config, ConfigError>,
+{
+    let (reader, mut stream) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+
+    let Some(line) = lines.next_line().await? else {
+        return Ok(());
+    };
+
+    let request = match serde_json::from_str::<Request>(&line) {
+        Ok(out) => out,
+        Err(e) => {
+            return write_line(&mut stream, &reply::<()>(None, Some(e.to_string()))).await;
+        }
+    };
+
+    log::debug!("ipc request: {:?}", request);
+
+    let error = match request {
+        Request::Get => None,
+        Request::Snooze { minutes } => {
+            notifier.snooze(minutes);
+            None
+        }
+        Request::Dismiss => {
+            notifier.dismiss();
+            None
+        }
+        Request::Pause { minutes } => {
+            notifier.pause(minutes);
+            None
+        }
+        Request::Resume => {
+            notifier.resume();
+            None
+        }
+        Request::Reload => match reload() {
+            Ok(config) => {
+                notifier.reload(&config);
+                None
+            }
+            Err(e) => Some(e.to_string()),
+        },
+        Request::Subscribe => {
+            let mut events = notifier.subscribe();
+            write_line(&mut stream, &reply(Some(notifier.state()), None)).await?;
+
+            let shutdown = notifier.shutdown_token();
+
+            loop {
+                let event = tokio::select! {
+                    event = events.recv() => event,
+                    _ = shutdown.cancelled() => return Ok(()),
+                };
+
+                match event {
+                    Ok(event) => write_line(&mut stream, &event).await?,
+                    Err(tokio::sync::broadcast::error::RecvError::Lagged(n)) => {
+                        log::warn!("ipc subscriber lagged by {n} events");
+                    }
+                    Err(tokio::sync::broadcast::error::RecvError::Closed) => return Ok(()),
+                }
+            }
+        }
+    };
+
+    write_line(&mut stream, &reply(Some(notifier.state()), error)).await
+}
+
+pub async fn serve<R>(notifier: &Rc<Notifier>, reload: R)
+where
+    R: Fn() -> Result<Config, ConfigError> + 'static,
+{
+    if let Err(e) = helper::ensure_runtime_dir() {
+        log::error!(
+            "{}: {e}, control socket disabled",
+            helper::runtime_dir().display()
+        );
+        return;
+    }
+
+    let path = socket_path();
+
+    if UnixStream::connect(&path).await.is_ok() {
+        log::warn!("{} is in use, control socket disabled", path.display());
+        return;
+    }
+
+    let _ = std::fs::remove_file(&path);
+
+    let listener = match UnixListener::bind(&path) {
+        Ok(out) => out,
+        Err(e) => {
+            log::error!("control socket {}: {e}", path.display());
+            return;
+        }
+    };
+
+    log::info!("control socket listening on {}", path.display());
+
+    let reload = Rc::new(reload);
+    let shutdown = notifier.shutdown_token();
+
+    loop {
+        let accepted = tokio::select! {
+            res = listener.accept() => res,
+            _ = shutdown.cancelled() => break,
+        };
+
+        match accepted {
+            Ok((stream, _)) => {
+                let notifier = notifier.clone();
+                let reload = reload.clone();
+
+                tokio::task::spawn_local(async move {
+                    if let Err(e) = handle_client(notifier, reload, stream).await {
+                        log::debug!("ipc client error: {e}");
+                    }
+                });
+            }
+            Err(e) => log::error!("control socket accept error: {e}"),
+        }
+    }
+
+    if let Err(e) = std::fs::remove_file(&path) {
+        log::debug!("control socket cleanup {}: {e}", path.display());
+    }
+}
+
+pub async fn client(request: &Request) {
+    if let Err(e) = helper::ensure_runtime_dir() {
+        log::error!("{}: {e}", helper::runtime_dir().display());
+        exit(1);
+    }
+
+    let path = socket_path();
+
+    let mut stream = match UnixStream::connect(&path).await {
+        Ok(out) => out,
+        Err(e) => {
+            log::error!("cannot connect to {}: {e}", path.display());
+            exit(1);
+        }
+    };
+
+    if let Err(e) = write_line(&mut stream, request).await {
+        log::error!("ipc write error: {e}");
+        exit(1);
+    }
+
+    let mut lines = BufReader::new(stream).lines();
+    let mut ok = true;
+
+    loop {
+        match lines.next_line().await {
+            Ok(Some(line)) => {
+                println!("{line}");
+
+                if !matches!(request, Request::Subscribe) {
+                    ok = serde_json::from_str::<serde_json::Value>(&line)
+                        .map(|value| value["ok"] == true)
+                        .unwrap_or(false);
+                    break;
+                }
+            }
+            Ok(None) => break,
+            Err(e) => {
+                log::error!("ipc read error: {e}");
+                exit(1);
+            }
+        }
+    }
+
+    if !ok {
+        exit(1);
+    }
+}
@@ -0,0 +1,230 @@
+use flate2::{write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RotateConfig {
+    pub max_size_kb: u64,
+    pub max_age_days: u64,
+    pub keep: usize,
+    pub compress: bool,
+}
+
+impl Default for RotateConfig {
+    fn default() -> Self {
+        RotateConfig {
+            max_size_kb: 1024,
+            max_age_days: 7,
+            keep: 5,
+            compress: true,
+        }
+    }
+}
+
+pub struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    created: SystemTime,
+    line_start: bool,
+    config: RotateConfig,
+}
+
+fn open_append(path: &Path) -> std::io::Result<(File, u64, SystemTime)> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let metadata = file.metadata()?;
+    let created = metadata
+        .created()
+        .or_else(|_| metadata.modified())
+        .unwrap_or_else(|_| SystemTime::now());
+
+    Ok((file, metadata.len(), created))
+}
+
+fn compress(path: &Path) -> std::io::Result<()> {
+    let mut gz_name = path.as_os_str().to_os_string();
+    gz_name.push(".gz");
+
+    let mut encoder = GzEncoder::new(File::create(gz_name)?, Compression::default());
+    std::io::copy(&mut File::open(path)?, &mut encoder)?;
+    encoder.finish()?;
+
+    std::fs::remove_file(path)
+}
+
+impl RotatingFile {
+    pub fn open<P: AsRef<Path>>(path: P, config: RotateConfig) -> std::io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        let (file, size, created) = open_append(&path)?;
+
+        let mut rotating = RotatingFile {
+            path,
+            file,
+            size,
+            created,
+            line_start: true,
+            config,
+        };
+
+        if rotating.is_due(0) {
+            rotating.rotate()?;
+        }
+
+        Ok(rotating)
+    }
+
+    fn rotated_path(&self, index: usize) -> PathBuf {
+        let mut name = self.path.as_os_str().to_os_string();
+        name.push(format!(".{index}"));
+
+        if self.config.compress {
+            name.push(".gz");
+        }
+
+        PathBuf::from(name)
+    }
+
+    fn is_due(&self, incoming: usize) -> bool {
+        if self.size == 0 {
+            return false;
+        }
+
+        let max_size = self.config.max_size_kb * 1024;
+        let max_age = Duration::from_secs(self.config.max_age_days * 24 * 60 * 60);
+        let age = self.created.elapsed().unwrap_or_default();
+
+        (max_size > 0 && self.size + incoming as u64 > max_size)
+            || (self.config.max_age_days > 0 && age > max_age)
+    }
+
+    fn rotate(&mut self) -> std::io::Result<()> {
+        self.file.flush()?;
+
+        if self.config.keep == 0 {
+            std::fs::remove_file(&self.path)?;
+        } else {
+            let _ = std::fs::remove_file(self.rotated_path(self.config.keep));
+
+            for index in (1..self.config.keep).rev() {
+                let from = self.rotated_path(index);
+                if from.exists() {
+                    std::fs::rename(from, self.rotated_path(index + 1))?;
+                }
+            }
+
+            let mut first = self.path.as_os_str().to_os_string();
+            first.push(".1");
+            std::fs::rename(&self.path, &first)?;
+
+            if self.config.compress {
+                compress(Path::new(&first))?;
+            }
+        }
+
+        let (file, size, _) = open_append(&self.path)?;
+        self.file = file;
+        self.size = size;
+        self.created = SystemTime::now();
+
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        // only rotate between lines so a record never spans two files
+        if self.line_start && self.is_due(buf.len()) {
+            if let Err(e) = self.rotate() {
+                eprintln!("log rotation of {} failed: {e}", self.path.display());
+            }
+        }
+
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        self.line_start = buf[..written].ends_with(b"\n");
+
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+
+    fn read_gz(path: &Path) -> String {
+        let mut text = String::new();
+        GzDecoder::new(File::open(path).unwrap())
+            .read_to_string(&mut text)
+            .unwrap();
+        text
+    }
+
+    #[test]
+    fn rotates_by_size_and_keeps_records_whole() {
+        let dir =
+            std::env::temp_dir().join(format!("battery-notify-test-{}-logs", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let path = dir.join("logs.txt");
+
+        let config = RotateConfig {
+            max_size_kb: 1,
+            max_age_days: 0,
+            keep: 3,
+            compress: true,
+        };
+        let mut file = RotatingFile::open(&path, config).unwrap();
+
+        // ~100 bytes a record, written in two parts like fern does
+        for record in 0..100 {
+            write!(file, "record {record:03} ").unwrap();
+            writeln!(file, "{}", "x".repeat(88)).unwrap();
+        }
+        file.flush().unwrap();
+
+        for index in 1..=3 {
+            assert!(dir.join(format!("logs.txt.{index}.gz")).exists(), "{index}");
+        }
+        assert!(!dir.join("logs.txt.4.gz").exists());
+        assert!(!dir.join("logs.txt.1").exists());
+
+        let mut records = Vec::new();
+
+        for text in (1..=3)
+            .rev()
+            .map(|index| read_gz(&dir.join(format!("logs.txt.{index}.gz"))))
+            .chain(std::iter::once(std::fs::read_to_string(&path).unwrap()))
+        {
+            // a record that starts below the limit may end past it
+            assert!(text.len() <= 1024 + 99);
+            assert!(text.ends_with('\n'));
+
+            for line in text.lines() {
+                assert_eq!(line.len(), 99, "split record: {line}");
+                records.push(line[7..10].parse::<u32>().unwrap());
+            }
+        }
+
+        // the oldest records were dropped, the rest are in order
+        assert!(records[0] > 0);
+        assert_eq!(records, (records[0]..100).collect::<Vec<_>>());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,340 @@
+use crate::{
+    battery::ChargeStatus,
+    helper,
+    log_file::{RotateConfig, RotatingFile},
+    notifier::Event,
+};
+use serde::{Deserialize, Serialize};
+use std::{io::Write, os::unix::net::UnixDatagram, path::Path, sync::Mutex};
+
+const JOURNALD_SOCKET: &str = "/run/systemd/journal/socket";
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LogSink {
+    Journald,
+    Syslog,
+    File,
+    Stdout,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Verbose,
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl LogLevel {
+    pub fn from_env() -> Option<Self> {
+        match std::env::var("LOGGING").ok()?.to_lowercase().as_str() {
+            "error" => Some(LogLevel::Error),
+            "warn" => Some(LogLevel::Warn),
+            "info" => Some(LogLevel::Info),
+            "debug" => Some(LogLevel::Debug),
+            "trace" => Some(LogLevel::Trace),
+            _ => None,
+        }
+    }
+}
+
+impl From<LogLevel> for log::LevelFilter {
+    fn from(value: LogLevel) -> Self {
+        match value {
+            LogLevel::Error => log::LevelFilter::Error,
+            LogLevel::Warn => log::LevelFilter::Warn,
+            LogLevel::Info => log::LevelFilter::Info,
+            LogLevel::Debug => log::LevelFilter::Debug,
+            LogLevel::Trace => log::LevelFilter::Trace,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    pub sinks: Vec<LogSink>,
+    pub format: LogFormat,
+    pub level: Option<LogLevel>,
+    pub file_name: String,
+    pub rotate: RotateConfig,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        LogConfig {
+            sinks: Vec::new(),
+            format: LogFormat::default(),
+            level: None,
+            file_name: "logs.txt".to_string(),
+            rotate: RotateConfig::default(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Serialize)]
+struct Fields {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    battery: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    percent: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status: Option<ChargeStatus>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    event: Option<&'static str>,
+}
+
+#[derive(Serialize)]
+struct JsonRecord<'a> {
+    timestamp: String,
+    level: &'a str,
+    target: &'a str,
+    message: String,
+    #[serde(flatten)]
+    fields: Fields,
+}
+
+static FIELDS: Mutex<Fields> = Mutex::new(Fields {
+    battery: None,
+    percent: None,
+    status: None,
+    event: None,
+});
+
+pub fn set_battery(battery: u32) {
+    FIELDS.lock().unwrap().battery = Some(battery);
+}
+
+pub fn set_percent(percent: u32) {
+    FIELDS.lock().unwrap().percent = Some(percent);
+}
+
+pub fn set_status(status: ChargeStatus) {
+    FIELDS.lock().unwrap().status = Some(status);
+}
+
+pub fn event(event: Event, message: &str) {
+    FIELDS.lock().unwrap().event = Some(event.name());
+    log::info!(
+        "{} notification: {}",
+        event.name(),
+        message.replace('\n', " ")
+    );
+    FIELDS.lock().unwrap().event = None;
+}
+
+struct JournaldLogger {
+    socket: UnixDatagram,
+    identifier: String,
+}
+
+fn append_field(buf: &mut Vec<u8>, key: &str, value: &str) {
+    if value.contains('\n') {
+        buf.extend_from_slice(key.as_bytes());
+        buf.push(b'\n');
+        buf.extend_from_slice(&(value.len() as u64).to_le_bytes());
+        buf.extend_from_slice(value.as_bytes());
+    } else {
+        buf.extend_from_slice(format!("{key}={value}").as_bytes());
+    }
+    buf.push(b'\n');
+}
+
+impl log::Log for JournaldLogger {
+    fn enabled(&self, _: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        let priority = match record.level() {
+            log::Level::Error => "3",
+            log::Level::Warn => "4",
+            log::Level::Info => "6",
+            log::Level::Debug | log::Level::Trace => "7",
+        };
+
+        let mut buf = Vec::new();
+        append_field(&mut buf, "MESSAGE", &record.args().to_string());
+        append_field(&mut buf, "PRIORITY", priority);
+        append_field(&mut buf, "SYSLOG_IDENTIFIER", &self.identifier);
+        append_field(&mut buf, "TARGET", record.target());
+
+        if let Some(file) = record.file() {
+            append_field(&mut buf, "CODE_FILE", file);
+        }
+
+        if let Some(line) = record.line() {
+            append_field(&mut buf, "CODE_LINE", &line.to_string());
+        }
+
+        {
+            let fields = FIELDS.lock().unwrap();
+
+            if let Some(battery) = fields.battery {
+                append_field(&mut buf, "BATTERY", &format!("BAT{battery}"));
+            }
+
+            if let Some(percent) = fields.percent {
+                append_field(&mut buf, "PERCENT", &percent.to_string());
+            }
+
+            if let Some(status) = fields.status {
+                append_field(&mut buf, "STATUS", &status.to_string());
+            }
+
+            if let Some(event) = fields.event {
+                append_field(&mut buf, "EVENT", event);
+            }
+        }
+
+        if let Err(e) = self.socket.send_to(&buf, JOURNALD_SOCKET) {
+            eprintln!("journald: {e}");
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+fn journald_logger() -> std::io::Result<Box<dyn log::Log>> {
+    if !Path::new(JOURNALD_SOCKET).exists() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("{JOURNALD_SOCKET} does not exist"),
+        ));
+    }
+
+    Ok(Box::new(JournaldLogger {
+        socket: UnixDatagram::unbound()?,
+        identifier: helper::prog_name().unwrap_or("battery-notify".to_string()),
+    }))
+}
+
+fn default_sinks() -> Vec<LogSink> {
+    if std::env::var_os("JOURNAL_STREAM").is_some() {
+        vec![LogSink::Journald]
+    } else {
+        vec![LogSink::File, LogSink::Stdout]
+    }
+}
+
+fn format_dispatch(format: LogFormat) -> fern::Dispatch {
+    match format {
+        LogFormat::Text => fern::Dispatch::new().format(|out, message, record| {
+            out.finish(format_args!(
+                "[{} {}] ({}): {}",
+                chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
+                record.level(),
+                helper::prog_name().unwrap_or("saltyfishie".to_string()),
+                message
+            ))
+        }),
+        LogFormat::Verbose => fern::Dispatch::new().format(|out, message, record| {
+            out.finish(format_args!(
+                "[{} {}] {}::{}\n- {}\n",
+                chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
+                record.level(),
+                record.file().unwrap_or("?"),
+                record.line().unwrap_or_default(),
+                message
+            ))
+        }),
+        LogFormat::Json => fern::Dispatch::new().format(|out, message, record| {
+            let line = serde_json::to_string(&JsonRecord {
+                timestamp: chrono::Local::now().to_rfc3339(),
+                level: record.level().as_str(),
+                target: record.target(),
+                message: message.to_string(),
+                fields: *FIELDS.lock().unwrap(),
+            })
+            .unwrap_or_default();
+
+            out.finish(format_args!("{line}"))
+        }),
+    }
+}
+
+fn log_file(config: &LogConfig) -> std::io::Result<Box<dyn Write + Send>> {
+    let dir = helper::log_dir().ok_or(std::io::Error::new(
+        std::io::ErrorKind::NotFound,
+        "neither XDG_CACHE_HOME nor HOME is set",
+    ))?;
+
+    Ok(Box::new(RotatingFile::open(
+        dir.join(&config.file_name),
+        config.rotate.clone(),
+    )?))
+}
+
+pub fn setup_cli_logging() {
+    fern::Dispatch::new()
+        .format(|out, message, record| out.finish(format_args!("{}: {}", record.level(), message)))
+        .level(log::LevelFilter::Warn)
+        .chain(std::io::stderr())
+        .apply()
+        .unwrap();
+}
+
+pub fn setup_logging(config: &LogConfig) {
+    let log_level = config.level.unwrap_or(LogLevel::Info).into();
+
+    let sinks = if config.sinks.is_empty() {
+        default_sinks()
+    } else {
+        config.sinks.clone()
+    };
+
+    let mut dispatch = fern::Dispatch::new()
+        .level(log_level)
+        .level_for("notify", log::LevelFilter::Info)
+        .level_for("mio", log::LevelFilter::Info)
+        .level_for("polling", log::LevelFilter::Info)
+        .level_for("async_io", log::LevelFilter::Info);
+
+    for sink in sinks {
+        dispatch = match sink {
+            LogSink::Journald => match journald_logger() {
+                Ok(logger) => dispatch.chain(logger),
+                Err(e) => {
+                    eprintln!("journald log sink unavailable: {e}");
+                    dispatch
+                }
+            },
+            LogSink::Syslog => match syslog::unix(syslog::Formatter3164 {
+                facility: syslog::Facility::LOG_USER,
+                hostname: None,
+                process: helper::prog_name().unwrap_or("battery-notify".to_string()),
+                pid: std::process::id(),
+            }) {
+                Ok(logger) => dispatch.chain(logger),
+                Err(e) => {
+                    eprintln!("syslog log sink unavailable: {e}");
+                    dispatch
+                }
+            },
+            LogSink::File => match log_file(config) {
+                Ok(file) => dispatch.chain(format_dispatch(config.format).chain(file)),
+                Err(e) => {
+                    eprintln!("file log sink unavailable: {e}");
+                    dispatch
+                }
+            },
+            LogSink::Stdout => {
+                dispatch.chain(format_dispatch(config.format).chain(std::io::stdout()))
+            }
+        };
+    }
+
+    dispatch.apply().unwrap();
+}
@@ -1,10 +1,32 @@
+mod actions;
+mod bar;
 mod battery;
+mod clock;
+mod commands;
+mod config;
+mod debounce;
 mod helper;
+mod hooks;
+mod icons;
+mod instance;
+mod ipc;
+mod log_file;
+mod logging;
+mod messages;
 mod notifier;
+mod power_profile;
+mod quiet;
+mod reminder;
+mod service;
+mod simulate;
+mod thresholds;
+mod trace;
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
+use config::Config;
 use notifier::Notifier;
-use std::{process::exit, rc::Rc};
+use std::{future::Future, path::PathBuf, process::exit, rc::Rc};
+use tokio_util::sync::CancellationToken;
 
 const UNPLUG_SOUND: &[u8] =
     std::include_bytes!("/home/saltyfishie/.local/share/sounds/big_sur/Bottle.wav");
@@ -16,42 +38,330 @@ const LOW_BATT_SOUND: &[u8] =
     std::include_bytes!("/home/saltyfishie/.local/share/sounds/big_sur/Funk.wav");
 
 #[derive(Parser, Debug)]
+#[command(version, about)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    #[command(flatten)]
+    pub args: UserArgs,
+}
+
+#[derive(clap::Args, Debug, Clone)]
 pub struct UserArgs {
-    #[arg(short = 'b', long = "batt", default_value_t = 0)]
-    pub battery_id: u32,
+    #[arg(short = 'b', long = "batt", global = true)]
+    pub battery_id: Option<u32>,
+
+    #[arg(short = 'l', long = "low", global = true)]
+    pub low_battery_percent: Option<u32>,
+
+    #[arg(short = 'c', long = "config", global = true)]
+    pub config_path: Option<PathBuf>,
 
-    #[arg(short = 'l', long = "low", default_value_t = 20)]
-    pub low_battery_percent: u32,
+    /// Log sinks, defaults to journald under systemd and file,stdout otherwise
+    #[arg(long = "log-sink", global = true, value_delimiter = ',')]
+    pub log_sinks: Option<Vec<logging::LogSink>>,
+
+    #[arg(long = "log-format", global = true)]
+    pub log_format: Option<logging::LogFormat>,
+
+    /// Log level, overrides the LOGGING environment variable
+    #[arg(long = "log-level", global = true)]
+    pub log_level: Option<logging::LogLevel>,
+
+    /// Development mode: alert at any level, trace logs to debug-logs.txt
+    #[arg(long, global = true)]
+    pub dev: bool,
 }
 
-#[tokio::main(flavor = "current_thread")]
-async fn main() {
-    helper::setup_logging();
-    let args = UserArgs::parse();
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Watch the battery and send notifications (default)
+    Run {
+        /// Take over from an already running instance
+        #[arg(long)]
+        replace: bool,
+    },
+
+    /// Print all batteries and adapters
+    Status {
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// List discovered power supplies
+    List {
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Fire the notification and sound of an event
+    Test {
+        event: notifier::Event,
+
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Stream status lines for waybar, i3blocks or polybar
+    Bar {
+        #[arg(short = 'f', long)]
+        format: Option<bar::BarFormat>,
+
+        /// Line template, e.g. "{icon} {percent}%"
+        #[arg(short = 't', long)]
+        template: Option<String>,
+
+        /// Comma separated icon ramp, from empty to full
+        #[arg(short = 'i', long, value_delimiter = ',')]
+        icons: Option<Vec<String>>,
+    },
+
+    /// Inspect the event hooks
+    Hooks {
+        #[command(subcommand)]
+        command: hooks::HooksCommand,
+    },
+
+    /// Read or set the charge thresholds, through pkexec when needed
+    Thresholds {
+        #[command(subcommand)]
+        command: thresholds::ThresholdsCommand,
+    },
 
-    #[cfg(not(debug_assertions))]
-    let notifier = Rc::new(Notifier::new(&args));
+    /// Control the running daemon
+    Ctl {
+        #[command(subcommand)]
+        request: ipc::Request,
+    },
 
-    #[cfg(debug_assertions)]
-    let notifier = Rc::new(Notifier::new(&UserArgs {
-        battery_id: args.battery_id,
-        low_battery_percent: 100,
-    }));
+    /// Install a systemd user unit for the daemon
+    InstallService {
+        /// Print the unit file instead of installing it
+        #[arg(long)]
+        print: bool,
+
+        /// Overwrite an existing unit file
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Check the environment the daemon depends on
+    Doctor {
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Record timestamped battery and adapter readings to a trace file
+    Record { output: PathBuf },
+
+    /// Feed a recorded trace through the notifier on a virtual clock
+    Replay {
+        trace: PathBuf,
+
+        /// Playback speed, e.g. 60x, or max to skip waiting
+        #[arg(long, default_value = "60x", value_parser = trace::parse_speed)]
+        speed: f64,
+
+        /// Show desktop notifications instead of printing them
+        #[arg(long)]
+        show: bool,
+    },
+
+    /// Run the daemon against a simulated battery
+    Simulate {
+        /// Starting charge, e.g. 40
+        #[arg(long, default_value = "40", value_parser = simulate::parse_percent)]
+        from: u32,
+
+        /// Charge rate, e.g. -10%/min
+        #[arg(
+            long,
+            default_value = "-10%/min",
+            allow_hyphen_values = true,
+            value_parser = simulate::parse_rate
+        )]
+        rate: f64,
+
+        /// Plug the charger in once the charge drops to this level
+        #[arg(long, value_parser = simulate::parse_percent)]
+        plug_at: Option<u32>,
+
+        /// TOML scenario of timed steps, replaces the rate options
+        #[arg(long, conflicts_with_all = ["from", "rate", "plug_at"])]
+        scenario: Option<PathBuf>,
+    },
+}
+
+fn load_config(args: &UserArgs) -> Config {
+    match Config::load_with_args(args) {
+        Ok(config) => config,
+        Err(e) => {
+            log::error!("config error: {e}");
+            exit(1);
+        }
+    }
+}
+
+async fn watch_until_shutdown<F>(watch: F, shutdown: &CancellationToken)
+where
+    F: Future<Output = notify::Result<()>>,
+{
+    if let Err(e) = watch.await {
+        log::error!("watch error: {:?}", e);
+    }
+
+    shutdown.cancel();
+}
+
+async fn run(args: &UserArgs, replace: bool) -> i32 {
+    let config = match Config::load_with_args(args) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("config error: {e}");
+            return 1;
+        }
+    };
+
+    logging::setup_logging(&config.log);
+
+    let _instance_lock = match instance::acquire(replace) {
+        Ok(out) => out,
+        Err(e) => {
+            log::error!("{e}");
+            exit(1);
+        }
+    };
+
+    if config.dev {
+        log::warn!("development mode enabled");
+    }
+
+    let notifier = Rc::new(Notifier::new(&config, true));
 
     let status_watch = notifier.make_status_watcher();
     let percent_watch = notifier.make_percent_watcher();
+    let hooks_watch = notifier.make_hooks_watcher();
+
+    let reload_args = args.clone();
+    let reload = move || Config::load_with_args(&reload_args);
 
-    match battery::Battery::get_live_percent(args.battery_id) {
+    let shutdown = notifier.shutdown_token();
+
+    let exit_code = match battery::Battery::get_live_percent(config.battery_id) {
         Ok(p) => {
-            let _ = tokio::join!(
-                percent_watch,
-                status_watch,
-                notifier.low_battery_notification(p)
-            );
+            let local = tokio::task::LocalSet::new();
+            let (.., exit_code) = local
+                .run_until(async {
+                    tokio::join!(
+                        watch_until_shutdown(percent_watch, &shutdown),
+                        watch_until_shutdown(status_watch, &shutdown),
+                        watch_until_shutdown(hooks_watch, &shutdown),
+                        notifier.low_battery_notification(p),
+                        ipc::serve(&notifier, reload),
+                        service::run(&notifier),
+                        helper::shutdown_signal(&shutdown)
+                    )
+                })
+                .await;
+            exit_code
         }
         Err(e) => {
             log::error!("{:?}", e);
-            exit(1);
+            1
         }
     };
+
+    notifier.close_notifications();
+    log::info!("exiting with status {exit_code}");
+    log::logger().flush();
+
+    exit_code
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    let cli = Cli::parse();
+
+    let command = cli.command.unwrap_or(Command::Run { replace: false });
+
+    if !matches!(command, Command::Run { .. } | Command::Simulate { .. }) {
+        logging::setup_cli_logging();
+    }
+
+    match command {
+        Command::Run { replace } => exit(run(&cli.args, replace).await),
+        Command::Status { json } => commands::status(json),
+        Command::List { json } => commands::list(json),
+        Command::Test { event, json } => commands::test(event, &load_config(&cli.args), json).await,
+        Command::Bar {
+            format,
+            template,
+            icons,
+        } => {
+            let mut config = load_config(&cli.args);
+
+            if let Some(template) = template {
+                config.bar.template = template;
+            }
+
+            if let Some(icons) = icons {
+                config.bar.icons = icons;
+            }
+
+            if let Err(e) = bar::run(&config, format.unwrap_or(config.bar.format)).await {
+                log::error!("bar error: {:?}", e);
+                exit(1);
+            }
+        }
+        Command::Hooks {
+            command: hooks::HooksCommand::List { json },
+        } => commands::hooks(&load_config(&cli.args), json),
+        Command::Thresholds { command } => thresholds::run(&load_config(&cli.args), command),
+        Command::Ctl { request } => ipc::client(&request).await,
+        Command::InstallService { print, force } => service::install(print, force),
+        Command::Doctor { json } => {
+            let config = Config::load(cli.args.config_path.as_deref())
+                .unwrap_or_default()
+                .with_args(&cli.args);
+            commands::doctor(&config, cli.args.config_path.as_deref(), json)
+        }
+        Command::Record { output } => trace::record(&load_config(&cli.args), &output).await,
+        Command::Replay { trace, speed, show } => {
+            trace::replay(&load_config(&cli.args), &trace, speed, show).await
+        }
+        Command::Simulate {
+            from,
+            rate,
+            plug_at,
+            scenario,
+        } => {
+            let mut config = match Config::load_with_args(&cli.args) {
+                Ok(config) => config,
+                Err(e) => {
+                    eprintln!("config error: {e}");
+                    exit(1);
+                }
+            };
+
+            if config.log.sinks.is_empty() {
+                config.log.sinks = vec![logging::LogSink::Stdout];
+            }
+
+            logging::setup_logging(&config.log);
+
+            let scenario = match scenario {
+                Some(path) => match simulate::Scenario::load(&path) {
+                    Ok(out) => out,
+                    Err(e) => {
+                        log::error!("scenario error: {e}");
+                        exit(1);
+                    }
+                },
+                None => simulate::Scenario::from_rate(from, rate, plug_at),
+            };
+
+            simulate::run(&config, &scenario).await
+        }
+    }
 }
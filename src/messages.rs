@@ -0,0 +1,219 @@
+use crate::{battery::ChargeStatus, helper, notifier::Event};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+const CATALOGS: &[(&str, &str)] = &[
+    ("en", include_str!("../locales/en.toml")),
+    ("de", include_str!("../locales/de.toml")),
+    ("es", include_str!("../locales/es.toml")),
+    ("fr", include_str!("../locales/fr.toml")),
+];
+
+/// Message templates per event. Used both for the `[messages]` config table
+/// and for the catalogs, where `language` is ignored.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MessagesConfig {
+    pub language: Option<String>,
+    pub plug: Option<String>,
+    pub unplug: Option<String>,
+    pub low: Option<String>,
+    pub full: Option<String>,
+    pub flap: Option<String>,
+    pub stalled: Option<String>,
+    pub limit: Option<String>,
+    pub unknown: Option<String>,
+}
+
+impl MessagesConfig {
+    fn get(&self, event: Event) -> Option<&str> {
+        match event {
+            Event::Plug => self.plug.as_deref(),
+            Event::Unplug => self.unplug.as_deref(),
+            Event::Low => self.low.as_deref(),
+            Event::Full => self.full.as_deref(),
+            Event::Flap => self.flap.as_deref(),
+            Event::Stalled => self.stalled.as_deref(),
+            Event::Limit => self.limit.as_deref(),
+            Event::Unknown => self.unknown.as_deref(),
+        }
+    }
+}
+
+/// Values the templates can refer to
+pub struct Values {
+    pub battery: u32,
+    pub percent: u32,
+    pub status: ChargeStatus,
+    pub eta: Option<chrono::Duration>,
+    pub watts: Option<f64>,
+    pub charger_watts: Option<f64>,
+    pub health: Option<u32>,
+    pub limit: Option<u32>,
+    pub start_percent: u32,
+    pub duration: chrono::Duration,
+}
+
+/// Language candidates from most to least specific, e.g. `de_AT`, `de`
+fn languages(config: &MessagesConfig) -> Vec<String> {
+    let locale = config.language.clone().or_else(|| {
+        ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .into_iter()
+            .filter_map(|var| std::env::var(var).ok())
+            .find(|value| !value.is_empty())
+    });
+
+    let Some(locale) = locale else {
+        return Vec::new();
+    };
+
+    // de_AT.UTF-8@euro -> de_AT
+    let locale = locale
+        .split(['.', '@'])
+        .next()
+        .unwrap_or_default()
+        .to_string();
+
+    let mut out = vec![locale.clone()];
+
+    if let Some((language, _)) = locale.split_once('_') {
+        out.push(language.to_string());
+    }
+
+    out
+}
+
+fn user_catalog(language: &str) -> Option<PathBuf> {
+    Some(
+        helper::config_dir()?
+            .join("locales")
+            .join(format!("{language}.toml")),
+    )
+}
+
+fn load_catalog(language: &str) -> Option<MessagesConfig> {
+    if let Some(path) = user_catalog(language).filter(|path| path.exists()) {
+        match std::fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|text| toml::from_str(&text).map_err(|e| e.to_string()))
+        {
+            Ok(catalog) => return Some(catalog),
+            Err(e) => log::warn!("message catalog {}: {e}", path.display()),
+        }
+    }
+
+    let (_, text) = CATALOGS.iter().find(|(name, _)| *name == language)?;
+    Some(toml::from_str(text).unwrap())
+}
+
+pub struct Messages {
+    layers: Vec<MessagesConfig>,
+}
+
+impl Messages {
+    /// Config templates first, then the catalogs of the configured or
+    /// environment language, then English
+    pub fn load(config: &MessagesConfig) -> Self {
+        let mut layers = vec![config.clone()];
+
+        for language in languages(config) {
+            if let Some(catalog) = load_catalog(&language) {
+                log::debug!("using {language} message catalog");
+                layers.push(catalog);
+            }
+        }
+
+        layers.extend(load_catalog("en"));
+
+        Messages { layers }
+    }
+
+    pub fn render(&self, event: Event, values: &Values) -> String {
+        let template = self
+            .layers
+            .iter()
+            .find_map(|layer| layer.get(event))
+            .unwrap_or_default();
+
+        let missing = || "?".to_string();
+
+        helper::render_template(
+            template,
+            &[
+                ("battery", values.battery.to_string()),
+                ("percent", values.percent.to_string()),
+                ("status", values.status.to_string()),
+                (
+                    "eta",
+                    values.eta.map_or_else(missing, helper::format_duration),
+                ),
+                (
+                    "watts",
+                    values.watts.map_or_else(missing, |w| format!("{w:.1}")),
+                ),
+                (
+                    "charger_watts",
+                    values
+                        .charger_watts
+                        .map_or_else(missing, |w| format!("{w:.0}")),
+                ),
+                (
+                    "health",
+                    values.health.map_or_else(missing, |h| h.to_string()),
+                ),
+                (
+                    "limit",
+                    values.limit.map_or_else(missing, |l| l.to_string()),
+                ),
+                ("start_percent", values.start_percent.to_string()),
+                ("duration", helper::format_duration(values.duration)),
+            ],
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EVENTS: [Event; 8] = [
+        Event::Plug,
+        Event::Unplug,
+        Event::Low,
+        Event::Full,
+        Event::Flap,
+        Event::Stalled,
+        Event::Limit,
+        Event::Unknown,
+    ];
+
+    #[test]
+    fn catalogs_cover_every_event() {
+        for (language, text) in CATALOGS {
+            let catalog: MessagesConfig =
+                toml::from_str(text).unwrap_or_else(|e| panic!("locales/{language}.toml: {e}"));
+
+            for event in EVENTS {
+                assert!(
+                    catalog.get(event).is_some_and(|text| !text.is_empty()),
+                    "locales/{language}.toml has no {event:?} message"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn language_falls_back_to_its_base() {
+        let config = MessagesConfig {
+            language: Some("de_AT.UTF-8@euro".to_string()),
+            ..MessagesConfig::default()
+        };
+        assert_eq!(languages(&config), ["de_AT", "de"]);
+
+        let config = MessagesConfig {
+            language: Some("fr".to_string()),
+            ..MessagesConfig::default()
+        };
+        assert_eq!(languages(&config), ["fr"]);
+    }
+}
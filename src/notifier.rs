@@ -1,22 +1,194 @@
-use crate::{battery, helper, UserArgs, LOW_BATT_SOUND, PLUG_SOUND, UNPLUG_SOUND};
-use notify::{RecursiveMode, Watcher};
-use notify_rust::{Hint, Notification};
-use rodio::Source;
-use std::{
-    cell::RefCell, future::Future, io::Cursor, path::Path, process::exit, rc::Rc, time::Duration,
+use crate::{
+    actions::{self, Action, ActionsConfig},
+    battery,
+    clock::{Clock, SystemClock},
+    config::Config,
+    debounce::{DebounceConfig, FlapTracker},
+    helper,
+    hooks::{self, HookData, HookEvent, Hooks},
+    icons::{self, IconsConfig},
+    logging,
+    messages::{Messages, Values},
+    power_profile::PowerProfiles,
+    quiet::{self, QuietConfig},
+    reminder::ReminderConfig,
+    LOW_BATT_SOUND, PLUG_SOUND, UNPLUG_SOUND,
 };
-use tokio::sync::watch;
+use notify_rust::{Hint, Notification, NotificationHandle};
+use rodio::Source;
+use serde::Serialize;
+use std::{cell::RefCell, collections::HashMap, io::Cursor, process::exit, rc::Rc, time::Duration};
+use tokio::sync::{broadcast, watch};
+use tokio_util::sync::CancellationToken;
+
+/// How often to look for a hooks.d created after startup
+const HOOKS_DIR_POLL: Duration = Duration::from_secs(2);
+
+/// A plugged in charger that does not charge is told apart from unplug
+/// and full, e.g. an underpowered USB-C charger or a dock
+fn classify_status(
+    status: battery::ChargeStatus,
+    percent: u32,
+    plugged: bool,
+    thresholds: &battery::ChargeThresholds,
+    watts: Option<f64>,
+) -> Event {
+    let start = thresholds.start.unwrap_or(95);
+    let end = thresholds.end.unwrap_or(100);
+
+    match status {
+        // unknown drain counts, discharging on AC is telling enough
+        battery::ChargeStatus::Discharging
+            if plugged && watts.is_none_or(|watts| watts.abs() > 0.0) =>
+        {
+            Event::Stalled
+        }
+        battery::ChargeStatus::NotCharging if plugged && percent < start.min(end) => Event::Stalled,
+        battery::ChargeStatus::NotCharging if end < 100 => Event::Limit,
+        _ => Event::from(status),
+    }
+}
 
 async fn play_embeded_sound(byte_data: &'static [u8], amplification: f32) {
-    let (_stream, handle) = rodio::OutputStream::try_default().unwrap();
-    let sink = rodio::Sink::try_new(&handle).unwrap();
-    let cursor = Cursor::new(byte_data);
-    let sound = rodio::Decoder::new(cursor).unwrap().amplify(amplification);
+    let (_stream, handle) = match rodio::OutputStream::try_default() {
+        Ok(out) => out,
+        Err(e) => {
+            log::warn!("cannot play sound, no audio output: {e}");
+            return;
+        }
+    };
+
+    let sink = match rodio::Sink::try_new(&handle) {
+        Ok(out) => out,
+        Err(e) => {
+            log::warn!("cannot play sound: {e}");
+            return;
+        }
+    };
+
+    let sound = match rodio::Decoder::new(Cursor::new(byte_data)) {
+        Ok(out) => out.amplify(amplification),
+        Err(e) => {
+            log::error!("cannot decode sound: {e}");
+            return;
+        }
+    };
 
     sink.append(sound);
     sink.sleep_until_end();
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Event {
+    Plug,
+    Unplug,
+    Low,
+    Full,
+    Flap,
+    Stalled,
+    Limit,
+    #[value(skip)]
+    Unknown,
+}
+
+impl Event {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::Plug => "plug",
+            Event::Unplug => "unplug",
+            Event::Low => "low",
+            Event::Full => "full",
+            Event::Flap => "flap",
+            Event::Stalled => "stalled",
+            Event::Limit => "limit",
+            Event::Unknown => "unknown",
+        }
+    }
+
+    pub fn is_critical(&self) -> bool {
+        *self == Event::Low
+    }
+
+    /// Notifications of one category replace each other
+    pub fn category(&self) -> &'static str {
+        match self {
+            Event::Low => "low",
+            Event::Plug
+            | Event::Unplug
+            | Event::Full
+            | Event::Flap
+            | Event::Stalled
+            | Event::Limit
+            | Event::Unknown => "power",
+        }
+    }
+
+    fn sound(&self) -> Option<(&'static [u8], f32)> {
+        match self {
+            Event::Plug => Some((PLUG_SOUND, 3.0)),
+            Event::Unplug => Some((UNPLUG_SOUND, 5.0)),
+            Event::Low => Some((LOW_BATT_SOUND, 5.0)),
+            Event::Stalled => Some((LOW_BATT_SOUND, 3.0)),
+            Event::Full | Event::Flap | Event::Limit | Event::Unknown => None,
+        }
+    }
+}
+
+impl From<battery::ChargeStatus> for Event {
+    fn from(value: battery::ChargeStatus) -> Self {
+        match value {
+            battery::ChargeStatus::Charging => Event::Plug,
+            battery::ChargeStatus::Discharging => Event::Unplug,
+            battery::ChargeStatus::NotCharging => Event::Full,
+            battery::ChargeStatus::Unknown => Event::Unknown,
+        }
+    }
+}
+
+pub fn event_notification(event: Event, body: &str) -> Notification {
+    let mut notification = Notification::new();
+    notification
+        .summary(&helper::prog_name().unwrap())
+        .body(body)
+        .hint(Hint::Transient(true));
+
+    // replace the previous notification of the category on servers that
+    // stack by tag instead of by id
+    let tag = format!("battery-notify-{}", event.category());
+    notification
+        .hint(Hint::Custom(
+            "x-canonical-private-synchronous".to_string(),
+            tag.clone(),
+        ))
+        .hint(Hint::Custom("x-dunst-stack-tag".to_string(), tag));
+
+    if event == Event::Low {
+        notification
+            .urgency(notify_rust::Urgency::Critical)
+            .timeout(Duration::from_millis(0));
+    }
+
+    notification
+}
+
+pub async fn announce(
+    event: Event,
+    notification: &Notification,
+) -> notify_rust::error::Result<NotificationHandle> {
+    let handle = notification.show();
+
+    if let Err(e) = &handle {
+        log::error!("{:?} notification error: {:?}", event, e);
+    }
+
+    if let Some((sound, amplification)) = event.sound() {
+        play_embeded_sound(sound, amplification).await;
+    }
+
+    handle
+}
+
 trait GetOwned<T> {
     fn get_owned(self, index: usize) -> Option<T>;
 }
@@ -31,18 +203,76 @@ impl<T> GetOwned<T> for Vec<T> {
     }
 }
 
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "lowercase")]
+pub enum DaemonEvent {
+    Percent { percent: u32 },
+    Status { status: battery::ChargeStatus },
+    Notification { kind: Event, body: String },
+    Reload,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NotifierState {
+    pub battery: u32,
+    pub percent: u32,
+    pub status: battery::ChargeStatus,
+    pub low_battery_percent: u32,
+    pub low_battery_notified: bool,
+    pub low_battery_dismissed: bool,
+    pub snoozed_until: Option<chrono::DateTime<chrono::Local>>,
+    pub paused_until: Option<chrono::DateTime<chrono::Local>>,
+    pub flaps: u32,
+}
+
+pub enum Delivery {
+    Shown(Box<NotificationHandle>),
+    Printed,
+}
+
 pub struct Notifier {
     battery_id: u32,
-    min_battery_percent: u32,
+    dry_run: bool,
+    /// Run hooks and switch power profiles, off while previewing
+    side_effects: bool,
+    /// Readings come from a trace, so the machine's sysfs says nothing
+    replaying: bool,
+    clock: Rc<dyn Clock>,
+    min_battery_percent: RefCell<u32>,
+    actions: RefCell<ActionsConfig>,
+    messages: RefCell<Messages>,
+    icons: RefCell<IconsConfig>,
+    quiet: RefCell<QuietConfig>,
+    reminders: RefCell<ReminderConfig>,
+    debounce: RefCell<DebounceConfig>,
+    hooks: RefCell<Hooks>,
+    power_profiles: Rc<PowerProfiles>,
+    critical_hook_fired: RefCell<bool>,
+    health_hook_fired: RefCell<bool>,
+    raw_status: RefCell<battery::ChargeStatus>,
+    status_generation: RefCell<u64>,
+    last_transition: RefCell<chrono::DateTime<chrono::Local>>,
+    flaps: RefCell<FlapTracker>,
+    replayed_adapters: RefCell<HashMap<String, bool>>,
     start_charge_percent: RefCell<u32>,
     start_charge_time: RefCell<chrono::DateTime<chrono::Local>>,
     battery_state: RefCell<battery::Battery>,
-    status_recv_opt: RefCell<Option<watch::Receiver<battery::ChargeStatus>>>,
+    status_tx: watch::Sender<battery::ChargeStatus>,
     low_battery_notified: RefCell<bool>,
+    low_battery_dismissed: RefCell<bool>,
+    close_low_battery: tokio::sync::Notify,
+    low_battery_handle: RefCell<Option<NotificationHandle>>,
+    notification_ids: RefCell<HashMap<&'static str, u32>>,
+    watchers_armed: RefCell<u32>,
+    armed: tokio::sync::Notify,
+    snoozed_until: RefCell<Option<chrono::DateTime<chrono::Local>>>,
+    paused_until: RefCell<Option<chrono::DateTime<chrono::Local>>>,
+    events: broadcast::Sender<DaemonEvent>,
+    shutdown: CancellationToken,
 }
 
 impl Notifier {
-    pub fn new(config: &UserArgs) -> Self {
+    pub fn new(config: &Config, side_effects: bool) -> Self {
         let battery = match battery::Batteries::default()
             .entry
             .get_owned(config.battery_id as usize)
@@ -56,105 +286,690 @@ impl Notifier {
 
         log::info!("Watching BAT{}", config.battery_id);
 
+        Self::with_state(
+            config,
+            battery,
+            Rc::new(SystemClock),
+            false,
+            side_effects,
+            false,
+        )
+    }
+
+    pub fn with_state(
+        config: &Config,
+        battery: battery::Battery,
+        clock: Rc<dyn Clock>,
+        dry_run: bool,
+        side_effects: bool,
+        replaying: bool,
+    ) -> Self {
+        logging::set_battery(config.battery_id);
+        logging::set_percent(battery.percent);
+        logging::set_status(battery.status);
+
         Notifier {
             battery_id: config.battery_id,
-            min_battery_percent: config.low_battery_percent,
-            start_charge_percent: RefCell::new(
-                battery::Battery::get_live_percent(config.battery_id).unwrap(),
-            ),
-            start_charge_time: RefCell::new(chrono::Local::now()),
+            dry_run,
+            side_effects,
+            replaying,
+            min_battery_percent: RefCell::new(config.low_battery_percent),
+            actions: RefCell::new(config.actions.clone()),
+            messages: RefCell::new(Messages::load(&config.messages)),
+            icons: RefCell::new(config.icons.clone()),
+            quiet: RefCell::new(config.quiet.clone()),
+            reminders: RefCell::new(config.reminders.clone()),
+            debounce: RefCell::new(config.debounce.clone()),
+            hooks: RefCell::new(Hooks::new(&config.hooks)),
+            power_profiles: Rc::new(PowerProfiles::new(
+                &config.power_profile,
+                clock.clone(),
+                side_effects,
+            )),
+            critical_hook_fired: RefCell::new(false),
+            health_hook_fired: RefCell::new(false),
+            raw_status: RefCell::new(battery.status),
+            status_generation: RefCell::new(0),
+            last_transition: RefCell::new(clock.now()),
+            flaps: RefCell::new(FlapTracker::default()),
+            replayed_adapters: RefCell::new(HashMap::new()),
+            start_charge_percent: RefCell::new(battery.percent),
+            start_charge_time: RefCell::new(clock.now()),
             battery_state: RefCell::new(battery),
-            status_recv_opt: RefCell::new(None),
+            status_tx: watch::channel(battery::ChargeStatus::Unknown).0,
             low_battery_notified: RefCell::new(false),
+            low_battery_dismissed: RefCell::new(false),
+            close_low_battery: tokio::sync::Notify::new(),
+            low_battery_handle: RefCell::new(None),
+            notification_ids: RefCell::new(HashMap::new()),
+            watchers_armed: RefCell::new(0),
+            armed: tokio::sync::Notify::new(),
+            snoozed_until: RefCell::new(None),
+            paused_until: RefCell::new(None),
+            events: broadcast::channel(16).0,
+            shutdown: CancellationToken::new(),
+            clock,
         }
     }
 
-    pub async fn low_battery_notification(self: &Rc<Self>, percent: u32) {
-        let mut status_recv_1;
-        {
-            status_recv_1 = self.status_recv_opt.borrow_mut().clone().unwrap();
+    fn now(&self) -> chrono::DateTime<chrono::Local> {
+        self.clock.now()
+    }
+
+    pub fn state(&self) -> NotifierState {
+        let battery_state = self.battery_state.borrow();
+
+        NotifierState {
+            battery: self.battery_id,
+            percent: battery_state.percent,
+            status: battery_state.status,
+            low_battery_percent: *self.min_battery_percent.borrow(),
+            low_battery_notified: *self.low_battery_notified.borrow(),
+            low_battery_dismissed: *self.low_battery_dismissed.borrow(),
+            snoozed_until: *self.snoozed_until.borrow(),
+            paused_until: *self.paused_until.borrow(),
+            flaps: self.flaps.borrow().flaps,
+        }
+    }
+
+    pub fn shutdown_token(&self) -> CancellationToken {
+        self.shutdown.clone()
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<DaemonEvent> {
+        self.events.subscribe()
+    }
+
+    fn emit(&self, event: DaemonEvent) {
+        // no subscribers is not an error
+        let _ = self.events.send(event);
+    }
+
+    pub fn snooze(self: &Rc<Self>, minutes: u32) {
+        log::info!("low battery notification snoozed for {minutes} min");
+        *self.snoozed_until.borrow_mut() =
+            Some(self.now() + chrono::Duration::minutes(minutes.into()));
+        self.close_low_battery.notify_waiters();
+
+        // alert again once the snooze runs out, even if the level holds
+        let notifier = self.clone();
+        tokio::task::spawn_local(async move {
+            tokio::select! {
+                _ = notifier.clock.sleep(Duration::from_secs(u64::from(minutes) * 60)) => {}
+                _ = notifier.shutdown.cancelled() => return,
+            }
+
+            let percent = notifier.battery_state.borrow().percent;
+            notifier.low_battery_notification(percent).await;
+        });
+    }
+
+    pub fn dismiss_until_next_level(&self) {
+        log::info!("low battery notification dismissed until the next level");
+        self.close_low_battery.notify_waiters();
+    }
+
+    pub fn dismiss(&self) {
+        log::info!("low battery notification dismissed until next charge");
+        *self.low_battery_dismissed.borrow_mut() = true;
+        self.close_low_battery.notify_waiters();
+    }
+
+    pub fn pause(&self, minutes: u32) {
+        log::info!("notifications paused for {minutes} min");
+        *self.paused_until.borrow_mut() =
+            Some(self.now() + chrono::Duration::minutes(minutes.into()));
+    }
+
+    pub fn resume(&self) {
+        log::info!("notifications resumed");
+        *self.paused_until.borrow_mut() = None;
+    }
+
+    pub fn reload(&self, config: &Config) {
+        if config.battery_id != self.battery_id {
+            log::warn!(
+                "battery_id change to BAT{} requires a restart",
+                config.battery_id
+            );
+        }
+
+        *self.min_battery_percent.borrow_mut() = config.low_battery_percent;
+        *self.actions.borrow_mut() = config.actions.clone();
+        *self.messages.borrow_mut() = Messages::load(&config.messages);
+        *self.icons.borrow_mut() = config.icons.clone();
+        *self.quiet.borrow_mut() = config.quiet.clone();
+        *self.reminders.borrow_mut() = config.reminders.clone();
+        *self.debounce.borrow_mut() = config.debounce.clone();
+        *self.hooks.borrow_mut() = Hooks::new(&config.hooks);
+        self.power_profiles.reload(&config.power_profile);
+        log::info!("config reloaded");
+        self.emit(DaemonEvent::Reload);
+    }
+
+    pub fn close_notifications(&self) {
+        if let Some(handle) = self.low_battery_handle.borrow_mut().take() {
+            log::debug!("closing low battery notification");
+            self.notification_ids
+                .borrow_mut()
+                .remove(Event::Low.category());
+            handle.close();
+        }
+    }
+
+    fn watcher_armed(&self) {
+        let mut watchers_armed = self.watchers_armed.borrow_mut();
+        *watchers_armed += 1;
+
+        if *watchers_armed == 2 {
+            self.armed.notify_one();
+        }
+    }
+
+    pub async fn wait_armed(&self) {
+        self.armed.notified().await;
+    }
+
+    fn is_paused(&self) -> bool {
+        matches!(*self.paused_until.borrow(), Some(until) if until > self.now())
+    }
+
+    async fn is_quiet(&self, event: Event) -> bool {
+        let quiet = self.quiet.borrow().clone();
+
+        if quiet.lets_through(event) {
+            return false;
+        }
+
+        quiet.in_schedule(self.now())
+            || (quiet.detect_dnd && !self.dry_run && quiet::desktop_dnd().await)
+    }
+
+    fn is_low_battery_suppressed(&self) -> bool {
+        *self.low_battery_dismissed.borrow()
+            || matches!(*self.snoozed_until.borrow(), Some(until) if until > self.now())
+    }
+
+    async fn notify(&self, event: Event, body: &str) -> Option<Delivery> {
+        if self.is_paused() {
+            log::info!("notifications paused, skipped {:?} notification", event);
+            return None;
+        }
+
+        if self.is_quiet(event).await {
+            log::info!("quiet hours, skipped {:?} notification", event);
+            return None;
+        }
+
+        logging::event(event, body);
+        self.emit(DaemonEvent::Notification {
+            kind: event,
+            body: body.to_string(),
+        });
+
+        if self.dry_run {
+            println!(
+                "{} {:<7} {}",
+                self.now().format("%Y-%m-%d %H:%M:%S"),
+                event.name(),
+                body.replace('\n', " | ")
+            );
+            return Some(Delivery::Printed);
+        }
+
+        let mut notification = event_notification(event, body);
+        self.decorate(&mut notification);
+
+        if event == Event::Low {
+            actions::add_actions(&mut notification, &self.actions.borrow());
+        }
+
+        if let Some(id) = self.notification_ids.borrow().get(event.category()) {
+            notification.id(*id);
+        }
+
+        let handle = announce(event, &notification).await.ok()?;
+        self.notification_ids
+            .borrow_mut()
+            .insert(event.category(), handle.id());
+
+        Some(Delivery::Shown(Box::new(handle)))
+    }
+
+    fn fire_hook(&self, event: HookEvent) {
+        let battery_state = self.battery_state.borrow();
+
+        let data = HookData {
+            event,
+            time: self.now(),
+            battery: self.battery_id,
+            percent: battery_state.percent,
+            status: battery_state.status,
+            online: self.adapter_online(),
+            health: self.live(|| battery::read_health(self.battery_id)),
+        };
+
+        log::debug!("hook event: {event}");
+        self.hooks.borrow().fire(&data, !self.side_effects);
+    }
+
+    /// Waits for running hooks and power profile switches
+    pub async fn wait_idle(&self) {
+        let idle = self.hooks.borrow().idle();
+        idle.await;
+        self.power_profiles.idle().await;
+    }
+
+    fn discharge_eta(&self) -> Option<chrono::Duration> {
+        let percent = self.battery_state.borrow().percent;
+        let dropped = self.start_charge_percent.borrow().checked_sub(percent)?;
+
+        if dropped == 0 {
+            return None;
+        }
+
+        let elapsed = self
+            .now()
+            .signed_duration_since(*self.start_charge_time.borrow());
+
+        Some(elapsed * percent as i32 / dropped as i32)
+    }
+
+    /// Sysfs reading, skipped when replaying since a trace has nothing to do
+    /// with the live battery
+    fn live<T>(&self, read: impl FnOnce() -> Option<T>) -> Option<T> {
+        if self.replaying {
+            None
+        } else {
+            read()
+        }
+    }
+
+    fn message(&self, event: Event) -> String {
+        let battery_state = self.battery_state.borrow();
+
+        let values = Values {
+            battery: self.battery_id,
+            percent: battery_state.percent,
+            status: battery_state.status,
+            eta: self.discharge_eta(),
+            watts: self.live(|| battery::read_watts(self.battery_id)),
+            charger_watts: self.live(battery::charger_watts),
+            health: self.live(|| battery::read_health(self.battery_id)),
+            limit: self.live(|| battery::read_thresholds(self.battery_id).end),
+            start_percent: *self.start_charge_percent.borrow(),
+            duration: self
+                .now()
+                .signed_duration_since(*self.start_charge_time.borrow()),
+        };
+
+        self.messages.borrow().render(event, &values)
+    }
+
+    fn decorate(&self, notification: &mut Notification) {
+        let battery_state = self.battery_state.borrow();
+
+        icons::decorate(
+            notification,
+            &self.icons.borrow(),
+            *self.min_battery_percent.borrow(),
+            battery_state.percent,
+            battery_state.status,
+        );
+    }
+
+    fn refresh_low_battery(&self) {
+        if let Some(handle) = self.low_battery_handle.borrow_mut().as_mut() {
+            let body = self.message(Event::Low);
+            log::trace!(
+                "low battery notification update: {}",
+                body.replace('\n', " ")
+            );
+
+            handle.body(&body);
+            self.decorate(handle);
+            handle.update();
+        }
+    }
+
+    fn listen_for_actions(self: &Rc<Self>, id: u32) {
+        let notifier = self.clone();
+
+        tokio::task::spawn_local(async move {
+            if let Some(action) = actions::wait_for_action(id).await {
+                notifier.handle_action(action).await;
+            }
+        });
+    }
+
+    async fn handle_action(self: &Rc<Self>, action: Action) {
+        log::info!("low battery notification action: {}", action.id());
+
+        let config = self.actions.borrow().clone();
+
+        match action {
+            Action::Snooze => self.snooze(config.snooze_minutes),
+            Action::Dismiss => self.dismiss_until_next_level(),
+            Action::Suspend => actions::run_command(&config.suspend_command).await,
+            Action::Settings => actions::run_command(&config.settings_command).await,
+        }
+    }
+
+    /// Shows or bumps the low battery alert, false if it was suppressed
+    async fn show_low_battery(self: &Rc<Self>) -> bool {
+        let body = self.message(Event::Low);
+
+        match self.notify(Event::Low, &body).await {
+            Some(Delivery::Shown(handle)) => {
+                let previous = self.low_battery_handle.borrow_mut().replace(*handle);
+                let id = self.low_battery_handle.borrow().as_ref().map(|h| h.id());
+
+                // a bumped alert keeps its id and its action listener
+                if previous.map(|h| h.id()) != id {
+                    self.listen_for_actions(id.unwrap_or_default());
+                }
+
+                true
+            }
+            Some(Delivery::Printed) => true,
+            None => false,
         }
+    }
+
+    /// Waits for the charger, a dismissal or shutdown, reminding on the way
+    async fn wait_low_battery(
+        self: &Rc<Self>,
+        status_recv: &mut watch::Receiver<battery::ChargeStatus>,
+        percent: u32,
+    ) {
+        let reminders = self.reminders.borrow().clone();
+        let mut events = self.subscribe();
+        let mut repeats = 0;
+        let mut reminded_at = percent;
+        let next_after = |now: chrono::DateTime<chrono::Local>| {
+            reminders
+                .interval()
+                .and_then(|interval| chrono::Duration::from_std(interval).ok())
+                .map(|interval| now + interval)
+        };
+        let mut next_reminder = next_after(self.now());
+
+        loop {
+            let due = next_reminder;
+            let remind = tokio::select! {
+                res = status_recv.wait_for(|status| {
+                    *status == battery::ChargeStatus::Charging
+                        || *status == battery::ChargeStatus::NotCharging
+                }) => {
+                    if let Err(e) = res {
+                        log::error!("status receiver error: {:?}", e);
+                    }
+                    return;
+                }
+                _ = self.close_low_battery.notified() => return,
+                _ = self.shutdown.cancelled() => return,
+                _ = async {
+                    match due {
+                        Some(deadline) => {
+                            let left = (deadline - self.now()).to_std().unwrap_or_default();
+                            self.clock.sleep(left).await
+                        }
+                        None => std::future::pending().await,
+                    }
+                }, if reminders.allows(repeats) => {
+                    // a deadline left in the past would fire again at once
+                    next_reminder = next_after(self.now());
+                    true
+                }
+                event = events.recv(), if reminders.allows(repeats) => matches!(
+                    event,
+                    Ok(DaemonEvent::Percent { percent }) if reminders.dropped_enough(reminded_at, percent)
+                ),
+            };
 
-        let low_battery = percent <= self.min_battery_percent;
+            if !remind || self.is_low_battery_suppressed() {
+                continue;
+            }
+
+            repeats += 1;
+            reminded_at = self.battery_state.borrow().percent;
+            next_reminder = next_after(self.now());
+
+            log::debug!("low battery reminder {repeats} @ {reminded_at}%");
+            self.show_low_battery().await;
+        }
+    }
+
+    pub async fn low_battery_notification(self: &Rc<Self>, percent: u32) {
+        let mut status_recv_1 = self.status_tx.subscribe();
+
+        let low_battery = percent <= *self.min_battery_percent.borrow();
         let is_charging;
         {
             is_charging = self.battery_state.borrow().status == battery::ChargeStatus::Charging;
         }
 
-        if low_battery && !is_charging && !*self.low_battery_notified.borrow() {
+        if low_battery
+            && !is_charging
+            && !*self.low_battery_notified.borrow()
+            && !self.is_low_battery_suppressed()
+        {
             log::debug!("low battery notification @ {percent}%");
 
+            if !self.critical_hook_fired.replace(true) {
+                self.fire_hook(HookEvent::Critical);
+            }
+
             {
                 let mut update_low_notified = self.low_battery_notified.borrow_mut();
                 *update_low_notified = true;
             }
 
-            let duration =
-                chrono::Local::now().signed_duration_since(*self.start_charge_time.borrow());
-            let seconds = duration.num_seconds() % 60;
-            let minutes = (duration.num_seconds() / 60) % 60;
-            let hours = (duration.num_seconds() / 60) / 60;
-            let duration_str = format!("{:02}:{:02}:{:02}", hours, minutes, seconds);
-
-            match Notification::new()
-                .summary(&format!("{}", helper::prog_name().unwrap()))
-                .body(&format!(
-                    "battery charge is low!\nduration from {}% : T-{}",
-                    self.start_charge_percent.borrow(),
-                    duration_str
-                ))
-                .hint(Hint::Transient(true))
-                .urgency(notify_rust::Urgency::Critical)
-                .timeout(Duration::from_millis(0))
-                .show()
-            {
-                Ok(handle) => {
-                    play_embeded_sound(LOW_BATT_SOUND, 5.0).await;
-
-                    if let Err(e) = status_recv_1
-                        .wait_for(|status| {
-                            *status == battery::ChargeStatus::Charging
-                                || *status == battery::ChargeStatus::NotCharging
-                        })
-                        .await
-                    {
-                        log::error!("status receiver error: {:?}", e);
-                    }
-                    handle.close();
-                    log::trace!("battery notification close!");
+            if self.show_low_battery().await {
+                self.wait_low_battery(&mut status_recv_1, percent).await;
+                self.close_notifications();
+                log::trace!("battery notification close!");
+            }
 
-                    {
-                        let mut update_low_notified = self.low_battery_notified.borrow_mut();
-                        *update_low_notified = false;
-                    }
-                }
-                Err(e) => {
-                    log::error!("percent notification error: {:?}", e);
-                }
+            {
+                let mut update_low_notified = self.low_battery_notified.borrow_mut();
+                *update_low_notified = false;
             }
 
             log::trace!("cleared low battery notification @ {percent}%");
         }
     }
 
+    pub fn handle_percent(self: &Rc<Self>, percent: u32) {
+        log::trace!("battery percent update: {percent}%");
+        logging::set_percent(percent);
+        let previous = std::mem::replace(&mut self.battery_state.borrow_mut().percent, percent);
+        self.emit(DaemonEvent::Percent { percent });
+
+        if self.battery_state.borrow().status == battery::ChargeStatus::Discharging {
+            self.power_profiles.on_battery(percent);
+        }
+
+        let crossed = self.hooks.borrow().crossed(previous, percent);
+        for event in crossed {
+            self.fire_hook(event);
+        }
+
+        let health_warning = self.hooks.borrow().health_warning_percent();
+        if self
+            .live(|| battery::read_health(self.battery_id))
+            .is_some_and(|health| health < health_warning)
+            && !self.health_hook_fired.replace(true)
+        {
+            self.fire_hook(HookEvent::HealthWarning);
+        }
+
+        self.refresh_low_battery();
+
+        // runs until charging, so it must not hold up further updates
+        let notifier = self.clone();
+        tokio::task::spawn_local(async move { notifier.low_battery_notification(percent).await });
+    }
+
+    /// Takes a raw status reading, which is announced once it has settled
+    pub fn handle_status(self: &Rc<Self>, new_status: battery::ChargeStatus) {
+        if *self.raw_status.borrow() == new_status {
+            return;
+        }
+
+        *self.raw_status.borrow_mut() = new_status;
+        log::debug!("raw battery status: {:?}", new_status);
+
+        let config = self.debounce.borrow().clone();
+
+        if self.flaps.borrow_mut().record(&config, self.now()) {
+            log::warn!(
+                "battery status is flapping, {} changes within {}s",
+                config.flap_threshold,
+                config.flap_window_secs
+            );
+
+            if config.warn_on_flap {
+                let notifier = self.clone();
+                tokio::task::spawn_local(async move {
+                    notifier
+                        .notify(Event::Flap, &notifier.message(Event::Flap))
+                        .await;
+                });
+            }
+        }
+
+        let generation = {
+            let mut generation = self.status_generation.borrow_mut();
+            *generation += 1;
+            *generation
+        };
+
+        let since_transition = self.now() - *self.last_transition.borrow();
+        let dwell_left = (config.min_dwell() - since_transition)
+            .to_std()
+            .unwrap_or_default();
+
+        let notifier = self.clone();
+        tokio::task::spawn_local(async move {
+            tokio::select! {
+                _ = notifier.clock.sleep(config.settle().max(dwell_left)) => {}
+                _ = notifier.shutdown.cancelled() => return,
+            }
+
+            // a newer reading restarted the wait
+            if *notifier.status_generation.borrow() == generation {
+                notifier.commit_status(new_status).await;
+            }
+        });
+    }
+
+    pub fn handle_adapter(&self, name: &str, online: bool) {
+        log::info!("adapter {name} online: {online}");
+        self.replayed_adapters
+            .borrow_mut()
+            .insert(name.to_string(), online);
+    }
+
+    fn adapter_online(&self) -> Option<bool> {
+        if !self.replaying {
+            return battery::adapter_online();
+        }
+
+        let adapters = self.replayed_adapters.borrow();
+        (!adapters.is_empty()).then(|| adapters.values().any(|online| *online))
+    }
+
+    fn status_event(&self, status: battery::ChargeStatus) -> Event {
+        let plugged = self.adapter_online() == Some(true);
+        let thresholds = self
+            .live(|| Some(battery::read_thresholds(self.battery_id)))
+            .unwrap_or_default();
+        let watts = if plugged && status == battery::ChargeStatus::Discharging {
+            self.live(|| battery::read_watts(self.battery_id))
+        } else {
+            None
+        };
+
+        let event = classify_status(
+            status,
+            self.battery_state.borrow().percent,
+            plugged,
+            &thresholds,
+            watts,
+        );
+
+        if event == Event::Stalled {
+            log::warn!(
+                "charger is connected but BAT{} is {}",
+                self.battery_id,
+                status
+            );
+        }
+
+        event
+    }
+
+    async fn commit_status(self: &Rc<Self>, new_status: battery::ChargeStatus) {
+        let old_status = self.battery_state.borrow().status;
+        self.battery_state.borrow_mut().status = new_status;
+
+        if new_status != old_status {
+            *self.last_transition.borrow_mut() = self.now();
+
+            // keeps the value even while no low battery alert is listening
+            self.status_tx.send_replace(new_status);
+
+            logging::set_status(new_status);
+            self.emit(DaemonEvent::Status { status: new_status });
+
+            if new_status == battery::ChargeStatus::Charging {
+                *self.low_battery_dismissed.borrow_mut() = false;
+                *self.critical_hook_fired.borrow_mut() = false;
+            }
+
+            if new_status == battery::ChargeStatus::Discharging {
+                *self.start_charge_time.borrow_mut() = self.now();
+                *self.start_charge_percent.borrow_mut() = self.battery_state.borrow().percent;
+                self.power_profiles
+                    .on_battery(self.battery_state.borrow().percent);
+            } else {
+                self.power_profiles.on_ac();
+            }
+
+            let event = self.status_event(new_status);
+
+            // hooks are automation, quiet hours and pauses do not hold them
+            if let Some(hook) = match event {
+                Event::Plug => Some(HookEvent::Plugged),
+                Event::Unplug => Some(HookEvent::Unplugged),
+                Event::Full | Event::Limit => Some(HookEvent::Full),
+                _ => None,
+            } {
+                self.fire_hook(hook);
+            }
+
+            self.notify(event, &self.message(event)).await;
+        }
+
+        log::info!("battery status update: {:?}", new_status);
+    }
+
     pub async fn make_percent_watcher(self: &Rc<Self>) -> notify::Result<()> {
         log::trace!("percent watcher started!");
 
-        let battery_percent_file = battery::percent_path(self.battery_id);
-        let percent_path = Path::new(battery_percent_file.as_str());
-
-        let (mut file_watcher, mut file_watcher_rx) = helper::file_watcher()?;
-        file_watcher.watch(percent_path.as_ref(), RecursiveMode::NonRecursive)?;
+        let (_file_watcher, mut file_watcher_rx) =
+            helper::watch_file(battery::percent_path(self.battery_id))?;
+        self.watcher_armed();
 
-        while let Some(res) = file_watcher_rx.recv().await {
+        while let Some(res) = tokio::select! {
+            res = file_watcher_rx.recv() => res,
+            _ = self.shutdown.cancelled() => None,
+        } {
             match res {
                 Ok(_) => {
                     let percent = battery::Battery::get_live_percent(self.battery_id).unwrap();
-                    log::trace!("battery percent update: {percent}%");
-                    self.low_battery_notification(percent).await;
-
-                    let mut battery_state = self.battery_state.borrow_mut();
-                    battery_state.percent = percent;
+                    self.handle_percent(percent);
                 }
                 Err(e) => println!("watch error: {:?}", e),
             }
@@ -163,121 +978,249 @@ impl Notifier {
         Ok(())
     }
 
-    pub fn make_status_watcher<'a>(
-        self: &'a Rc<Self>,
-    ) -> impl Future<Output = notify::Result<()>> + 'a {
-        log::trace!("status watcher started!");
+    /// Rescans the hook scripts as they change, waiting for hooks.d to be
+    /// created when there is none yet
+    pub async fn make_hooks_watcher(self: &Rc<Self>) -> notify::Result<()> {
+        log::trace!("hooks watcher started!");
 
-        let (tx, rx) = watch::channel(battery::ChargeStatus::Unknown);
+        let Some(dir) = hooks::scripts_dir() else {
+            log::debug!("no hook scripts directory to watch");
+            self.shutdown.cancelled().await;
+            return Ok(());
+        };
 
-        {
-            let mut local_rx = self.status_recv_opt.borrow_mut();
-            *local_rx = Some(rx);
-        }
+        loop {
+            if !dir.is_dir() {
+                tokio::select! {
+                    _ = tokio::time::sleep(HOOKS_DIR_POLL) => continue,
+                    _ = self.shutdown.cancelled() => return Ok(()),
+                }
+            }
 
-        async move {
-            let batt_id = self.battery_id;
+            // scripts may have been added before the watch started
+            self.hooks.borrow_mut().rescan();
 
-            let battery_status_file = battery::status_path(batt_id);
-            let status_path = Path::new(battery_status_file.as_str());
+            let (_file_watcher, mut file_watcher_rx) = helper::watch_dir(&dir)?;
 
-            let (mut file_watcher, mut file_watcher_rx) = helper::file_watcher()?;
-            file_watcher.watch(status_path.as_ref(), RecursiveMode::NonRecursive)?;
+            while dir.is_dir() {
+                let res = tokio::select! {
+                    res = file_watcher_rx.recv() => res,
+                    _ = self.shutdown.cancelled() => return Ok(()),
+                };
 
-            while let Some(res) = file_watcher_rx.recv().await {
                 match res {
-                    Ok(_) => {
-                        let new_status = battery::Battery::get_live_status(batt_id).unwrap();
-
-                        if new_status != self.battery_state.borrow().status {
-                            match new_status {
-                                battery::ChargeStatus::Charging => {
-                                    if let Err(e) = tx.send(battery::ChargeStatus::Charging) {
-                                        log::error!("status sender error: {}", e);
-                                    }
-
-                                    if let Err(e) = Notification::new()
-                                        .summary(&format!("{}", helper::prog_name().unwrap()))
-                                        .body("The battery has started charging!")
-                                        .hint(Hint::Transient(true))
-                                        .show()
-                                    {
-                                        log::error!("status notification error: {:?}", e);
-                                    }
-
-                                    play_embeded_sound(PLUG_SOUND, 3.0).await;
-                                }
-
-                                battery::ChargeStatus::Discharging => {
-                                    if let Err(e) = tx.send(battery::ChargeStatus::Discharging) {
-                                        log::error!("status watch channel error: {}", e);
-                                    }
-
-                                    {
-                                        let mut start_charge_time =
-                                            self.start_charge_time.borrow_mut();
-                                        let mut start_charge_percent =
-                                            self.start_charge_percent.borrow_mut();
-
-                                        *start_charge_time = chrono::Local::now();
-                                        *start_charge_percent =
-                                            battery::Battery::get_live_percent(batt_id).unwrap();
-                                    }
-
-                                    if let Err(e) = Notification::new()
-                                        .summary(&format!("{}", helper::prog_name().unwrap()))
-                                        .body("The battery has stopped charging!")
-                                        .hint(Hint::Transient(true))
-                                        .show()
-                                    {
-                                        log::error!("status notification error: {:?}", e);
-                                    }
-
-                                    play_embeded_sound(UNPLUG_SOUND, 5.0).await;
-                                }
-
-                                battery::ChargeStatus::NotCharging => {
-                                    if let Err(e) = tx.send(battery::ChargeStatus::NotCharging) {
-                                        log::error!("status watch channel error: {}", e);
-                                    }
-
-                                    if let Err(e) = Notification::new()
-                                        .summary(&format!("{}", helper::prog_name().unwrap()))
-                                        .body("The battery is fully charged!")
-                                        .hint(Hint::Transient(true))
-                                        .show()
-                                    {
-                                        log::error!("status notification error: {:?}", e);
-                                    }
-                                }
-
-                                battery::ChargeStatus::Unknown => {
-                                    if let Err(e) = tx.send(battery::ChargeStatus::Unknown) {
-                                        log::error!("status watch channel error: {}", e);
-                                    }
-
-                                    if let Err(e) = Notification::new()
-                                        .summary(&format!("{}", helper::prog_name().unwrap()))
-                                        .body("The battery status is currently unknown!")
-                                        .hint(Hint::Transient(true))
-                                        .show()
-                                    {
-                                        log::error!("status notification error: {:?}", e);
-                                    }
-                                }
-                            };
-                        }
-
-                        log::info!("battery status update: {:?}", new_status);
-
-                        let mut battery_state = self.battery_state.borrow_mut();
-                        battery_state.status = new_status;
+                    Some(Ok(_)) => {
+                        log::info!("hook scripts changed");
+                        self.hooks.borrow_mut().rescan();
                     }
-                    Err(e) => println!("watch error: {:?}", e),
+                    Some(Err(e)) => log::error!("watch error: {:?}", e),
+                    None => return Ok(()),
                 }
             }
 
-            Ok(())
+            log::info!("{} was removed", dir.display());
+            self.hooks.borrow_mut().rescan();
         }
     }
+
+    pub async fn make_status_watcher(self: &Rc<Self>) -> notify::Result<()> {
+        log::trace!("status watcher started!");
+
+        let batt_id = self.battery_id;
+
+        let (_file_watcher, mut file_watcher_rx) =
+            helper::watch_file(battery::status_path(batt_id))?;
+        self.watcher_armed();
+
+        while let Some(res) = tokio::select! {
+            res = file_watcher_rx.recv() => res,
+            _ = self.shutdown.cancelled() => None,
+        } {
+            match res {
+                Ok(_) => {
+                    let new_status = battery::Battery::get_live_status(batt_id).unwrap();
+                    self.battery_state.borrow_mut().percent =
+                        battery::Battery::get_live_percent(batt_id).unwrap();
+                    self.handle_status(new_status);
+                }
+                Err(e) => println!("watch error: {:?}", e),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+
+    fn at(clock: &ManualClock, secs: i64) -> chrono::DateTime<chrono::Local> {
+        clock.now() + chrono::Duration::seconds(secs)
+    }
+
+    fn notifier(clock: &Rc<ManualClock>, config: &Config, percent: u32) -> Rc<Notifier> {
+        let battery = battery::Battery {
+            id: 0,
+            percent,
+            status: battery::ChargeStatus::Discharging,
+        };
+
+        Rc::new(Notifier::with_state(
+            config,
+            battery,
+            clock.clone(),
+            true,
+            false,
+            true,
+        ))
+    }
+
+    #[tokio::test]
+    async fn status_waits_for_the_clock() {
+        let clock = Rc::new(ManualClock::new(chrono::Local::now()));
+        let notifier = notifier(&clock, &Config::default(), 50);
+
+        tokio::task::LocalSet::new()
+            .run_until(async {
+                notifier.handle_status(battery::ChargeStatus::Charging);
+
+                // the default 5s dwell outlasts the 3s settle
+                clock.advance_to(at(&clock, 4)).await;
+                assert_eq!(notifier.state().status, battery::ChargeStatus::Discharging);
+
+                clock.advance_to(at(&clock, 1)).await;
+                assert_eq!(notifier.state().status, battery::ChargeStatus::Charging);
+
+                notifier.shutdown_token().cancel();
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn flicker_is_not_announced() {
+        let clock = Rc::new(ManualClock::new(chrono::Local::now()));
+        let notifier = notifier(&clock, &Config::default(), 50);
+
+        tokio::task::LocalSet::new()
+            .run_until(async {
+                notifier.handle_status(battery::ChargeStatus::Charging);
+                clock.advance_to(at(&clock, 1)).await;
+                notifier.handle_status(battery::ChargeStatus::Discharging);
+
+                clock.advance_to(at(&clock, 60)).await;
+                assert_eq!(notifier.state().status, battery::ChargeStatus::Discharging);
+                assert_eq!(notifier.state().flaps, 0);
+
+                notifier.shutdown_token().cancel();
+            })
+            .await;
+    }
+
+    fn low_alerts(events: &mut broadcast::Receiver<DaemonEvent>) -> usize {
+        std::iter::from_fn(|| events.try_recv().ok())
+            .filter(|event| {
+                matches!(
+                    event,
+                    DaemonEvent::Notification {
+                        kind: Event::Low,
+                        ..
+                    }
+                )
+            })
+            .count()
+    }
+
+    #[tokio::test]
+    async fn reminds_on_time_and_on_drops_up_to_the_cap() {
+        let clock = Rc::new(ManualClock::new(chrono::Local::now()));
+        let mut config = Config::default();
+        config.reminders = ReminderConfig {
+            interval_minutes: 5,
+            every_percent: 2,
+            max_repeats: 2,
+        };
+        let notifier = notifier(&clock, &config, 10);
+        let mut events = notifier.subscribe();
+
+        tokio::task::LocalSet::new()
+            .run_until(async {
+                let alert = notifier.clone();
+                tokio::task::spawn_local(async move { alert.low_battery_notification(10).await });
+
+                clock.advance_to(at(&clock, 1)).await;
+                assert_eq!(low_alerts(&mut events), 1);
+
+                clock.advance_to(at(&clock, 4 * 60)).await;
+                assert_eq!(low_alerts(&mut events), 0);
+
+                // interval_minutes
+                clock.advance_to(at(&clock, 60)).await;
+                assert_eq!(low_alerts(&mut events), 1);
+
+                // every_percent, counted from the last reminder
+                notifier.handle_percent(9);
+                clock.advance_to(at(&clock, 1)).await;
+                assert_eq!(low_alerts(&mut events), 0);
+
+                notifier.handle_percent(8);
+                clock.advance_to(at(&clock, 1)).await;
+                assert_eq!(low_alerts(&mut events), 1);
+
+                // max_repeats
+                notifier.handle_percent(4);
+                clock.advance_to(at(&clock, 30 * 60)).await;
+                assert_eq!(low_alerts(&mut events), 0);
+
+                notifier.shutdown_token().cancel();
+            })
+            .await;
+    }
+
+    #[test]
+    fn charger_that_does_not_charge_is_stalled() {
+        use battery::{ChargeStatus, ChargeThresholds};
+
+        let none = ChargeThresholds::default();
+        let desk = ChargeThresholds {
+            start: Some(75),
+            end: Some(80),
+            ..ChargeThresholds::default()
+        };
+
+        // discharging on AC, with drain or no reading of it
+        assert_eq!(
+            classify_status(ChargeStatus::Discharging, 60, true, &none, None),
+            Event::Stalled
+        );
+        assert_eq!(
+            classify_status(ChargeStatus::Discharging, 60, true, &none, Some(-4.2)),
+            Event::Stalled
+        );
+        assert_eq!(
+            classify_status(ChargeStatus::Discharging, 60, false, &none, None),
+            Event::Unplug
+        );
+
+        // not charging below the start threshold
+        assert_eq!(
+            classify_status(ChargeStatus::NotCharging, 50, true, &desk, None),
+            Event::Stalled
+        );
+        assert_eq!(
+            classify_status(ChargeStatus::NotCharging, 90, true, &none, None),
+            Event::Stalled
+        );
+
+        // not charging at the limit
+        assert_eq!(
+            classify_status(ChargeStatus::NotCharging, 80, true, &desk, None),
+            Event::Limit
+        );
+        assert_eq!(
+            classify_status(ChargeStatus::NotCharging, 100, true, &none, None),
+            Event::Full
+        );
+    }
 }
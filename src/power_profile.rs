@@ -0,0 +1,380 @@
+use crate::clock::Clock;
+use serde::{Deserialize, Serialize};
+use std::{cell::RefCell, rc::Rc};
+
+/// power-profiles-daemon names, the UPower one since 0.20
+const SERVICES: [(&str, &str); 2] = [
+    (
+        "org.freedesktop.UPower.PowerProfiles",
+        "/org/freedesktop/UPower/PowerProfiles",
+    ),
+    ("net.hadess.PowerProfiles", "/net/hadess/PowerProfiles"),
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PowerProfileConfig {
+    pub enabled: bool,
+    /// Switch once unplugged at or below this percentage
+    pub below_percent: u32,
+    pub battery_profile: String,
+}
+
+impl Default for PowerProfileConfig {
+    fn default() -> Self {
+        PowerProfileConfig {
+            enabled: false,
+            below_percent: 30,
+            battery_profile: "power-saver".to_string(),
+        }
+    }
+}
+
+/// Talks to power-profiles-daemon under whichever name it answers on
+async fn proxy(connection: &zbus::Connection) -> zbus::Result<zbus::Proxy<'static>> {
+    let mut error = None;
+
+    for (name, path) in SERVICES {
+        let proxy = zbus::ProxyBuilder::<zbus::Proxy>::new_bare(connection)
+            .destination(name)?
+            .path(path)?
+            .interface(name)?
+            .cache_properties(zbus::CacheProperties::No)
+            .build()
+            .await?;
+
+        match proxy.get_property::<String>("ActiveProfile").await {
+            Ok(_) => return Ok(proxy),
+            Err(e) => error = Some(e),
+        }
+    }
+
+    Err(error.unwrap_or(zbus::Error::Unsupported))
+}
+
+async fn active_profile(connection: &zbus::Connection) -> zbus::Result<String> {
+    proxy(connection).await?.get_property("ActiveProfile").await
+}
+
+async fn set_active_profile(connection: &zbus::Connection, profile: &str) -> zbus::Result<()> {
+    Ok(proxy(connection)
+        .await?
+        .set_property("ActiveProfile", profile)
+        .await?)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum State {
+    /// Nothing done since the last time on AC
+    Idle,
+    /// On battery without a profile to restore, e.g. the daemon is missing
+    /// or the user already picked the battery profile
+    Kept,
+    Switched {
+        previous: String,
+        applied: String,
+    },
+}
+
+pub struct PowerProfiles {
+    config: RefCell<PowerProfileConfig>,
+    /// Switch for real, simulate and replay only print
+    side_effects: bool,
+    clock: Rc<dyn Clock>,
+    /// The system bus once connected, which follows `DBUS_SYSTEM_BUS_ADDRESS`
+    connection: RefCell<Option<zbus::Connection>>,
+    state: RefCell<State>,
+    // a switch and its restore must not overtake each other
+    lock: tokio::sync::Mutex<()>,
+}
+
+impl PowerProfiles {
+    pub fn new(config: &PowerProfileConfig, clock: Rc<dyn Clock>, side_effects: bool) -> Self {
+        PowerProfiles {
+            config: RefCell::new(config.clone()),
+            side_effects,
+            clock,
+            connection: RefCell::new(None),
+            state: RefCell::new(State::Idle),
+            lock: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    pub fn reload(&self, config: &PowerProfileConfig) {
+        *self.config.borrow_mut() = config.clone();
+    }
+
+    async fn connection(&self) -> zbus::Result<zbus::Connection> {
+        if let Some(connection) = self.connection.borrow().clone() {
+            return Ok(connection);
+        }
+
+        let connection = zbus::Connection::system().await?;
+        *self.connection.borrow_mut() = Some(connection.clone());
+        Ok(connection)
+    }
+
+    fn print(&self, profile: &str) {
+        println!(
+            "{} {:<7} {profile}",
+            self.clock.now().format("%Y-%m-%d %H:%M:%S"),
+            "profile"
+        );
+    }
+
+    /// Waits for a pending switch or restore
+    pub async fn idle(&self) {
+        tokio::task::yield_now().await;
+        let _guard = self.lock.lock().await;
+    }
+
+    /// Switches to the battery profile once the charge is low enough
+    pub fn on_battery(self: &Rc<Self>, percent: u32) {
+        let config = self.config.borrow().clone();
+
+        if !config.enabled || percent > config.below_percent || *self.state.borrow() != State::Idle
+        {
+            return;
+        }
+
+        *self.state.borrow_mut() = State::Kept;
+
+        let profiles = self.clone();
+        tokio::task::spawn_local(async move {
+            let _guard = profiles.lock.lock().await;
+            profiles.switch(&config.battery_profile).await;
+        });
+    }
+
+    async fn switch(&self, profile: &str) {
+        if !self.side_effects {
+            self.print(profile);
+            // a preview has no profile to go back to
+            *self.state.borrow_mut() = State::Switched {
+                previous: String::new(),
+                applied: profile.to_string(),
+            };
+            return;
+        }
+
+        let connection = match self.connection().await {
+            Ok(out) => out,
+            Err(e) => {
+                log::warn!("cannot connect to the system bus: {e}");
+                return;
+            }
+        };
+
+        let previous = match active_profile(&connection).await {
+            Ok(out) => out,
+            Err(e) => {
+                log::warn!("cannot read the power profile: {e}");
+                return;
+            }
+        };
+
+        if previous == profile {
+            log::debug!("power profile is {profile} already");
+            return;
+        }
+
+        match set_active_profile(&connection, profile).await {
+            Ok(_) => {
+                log::info!("power profile {previous} -> {profile}");
+                *self.state.borrow_mut() = State::Switched {
+                    previous,
+                    applied: profile.to_string(),
+                };
+            }
+            Err(e) => log::warn!("cannot switch to power profile {profile}: {e}"),
+        }
+    }
+
+    /// Goes back to the profile from before the switch, unless the user
+    /// picked another one meanwhile
+    pub fn on_ac(self: &Rc<Self>) {
+        if *self.state.borrow() == State::Idle {
+            return;
+        }
+
+        let profiles = self.clone();
+        tokio::task::spawn_local(async move {
+            let _guard = profiles.lock.lock().await;
+            let state = profiles.state.replace(State::Idle);
+
+            if let State::Switched { previous, applied } = state {
+                profiles.restore(&previous, &applied).await;
+            }
+        });
+    }
+
+    async fn restore(&self, previous: &str, applied: &str) {
+        if !self.side_effects {
+            self.print("restore");
+            return;
+        }
+
+        let connection = match self.connection().await {
+            Ok(out) => out,
+            Err(e) => {
+                log::warn!("cannot connect to the system bus: {e}");
+                return;
+            }
+        };
+
+        match active_profile(&connection).await {
+            Ok(current) if current != applied => {
+                log::info!("power profile was changed to {current}, keeping it");
+            }
+            Ok(_) => match set_active_profile(&connection, previous).await {
+                Ok(_) => log::info!("power profile {applied} -> {previous}"),
+                Err(e) => log::warn!("cannot restore power profile {previous}: {e}"),
+            },
+            Err(e) => log::warn!("cannot read the power profile: {e}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::SystemClock;
+
+    /// Stands in for power-profiles-daemon under its older name
+    struct MockDaemon {
+        profile: String,
+    }
+
+    #[zbus::dbus_interface(name = "net.hadess.PowerProfiles")]
+    impl MockDaemon {
+        #[dbus_interface(property)]
+        fn active_profile(&self) -> String {
+            self.profile.clone()
+        }
+
+        #[dbus_interface(property)]
+        fn set_active_profile(&mut self, profile: String) {
+            self.profile = profile;
+        }
+    }
+
+    /// A private peer-to-peer bus with the mock daemon on the other end
+    async fn mock_daemon(profile: &str) -> (zbus::Connection, zbus::Connection) {
+        let (server, client) = std::os::unix::net::UnixStream::pair().unwrap();
+        let guid = zbus::Guid::generate();
+        let daemon = MockDaemon {
+            profile: profile.to_string(),
+        };
+
+        tokio::try_join!(
+            zbus::ConnectionBuilder::unix_stream(server)
+                .server(&guid)
+                .p2p()
+                .serve_at("/net/hadess/PowerProfiles", daemon)
+                .unwrap()
+                .build(),
+            zbus::ConnectionBuilder::unix_stream(client).p2p().build(),
+        )
+        .unwrap()
+    }
+
+    fn profiles(connection: &zbus::Connection) -> Rc<PowerProfiles> {
+        let config = PowerProfileConfig {
+            enabled: true,
+            ..PowerProfileConfig::default()
+        };
+        let profiles = PowerProfiles::new(&config, Rc::new(SystemClock), true);
+        *profiles.connection.borrow_mut() = Some(connection.clone());
+
+        Rc::new(profiles)
+    }
+
+    async fn unplug_and_plug(
+        profiles: &Rc<PowerProfiles>,
+        connection: &zbus::Connection,
+        user_pick: Option<&str>,
+    ) -> (String, String) {
+        profiles.on_battery(25);
+        profiles.idle().await;
+        let on_battery = active_profile(connection).await.unwrap();
+
+        if let Some(profile) = user_pick {
+            set_active_profile(connection, profile).await.unwrap();
+        }
+
+        profiles.on_ac();
+        profiles.idle().await;
+        let on_ac = active_profile(connection).await.unwrap();
+
+        (on_battery, on_ac)
+    }
+
+    #[tokio::test]
+    async fn switches_and_restores() {
+        let (_daemon, connection) = mock_daemon("balanced").await;
+        let profiles = profiles(&connection);
+
+        tokio::task::LocalSet::new()
+            .run_until(async {
+                // above below_percent nothing happens
+                profiles.on_battery(50);
+                profiles.idle().await;
+                assert_eq!(active_profile(&connection).await.unwrap(), "balanced");
+
+                assert_eq!(
+                    unplug_and_plug(&profiles, &connection, None).await,
+                    ("power-saver".to_string(), "balanced".to_string())
+                );
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn keeps_the_users_pick() {
+        let (_daemon, connection) = mock_daemon("balanced").await;
+        let profiles = profiles(&connection);
+
+        tokio::task::LocalSet::new()
+            .run_until(async {
+                assert_eq!(
+                    unplug_and_plug(&profiles, &connection, Some("performance")).await,
+                    ("power-saver".to_string(), "performance".to_string())
+                );
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn leaves_an_existing_battery_profile() {
+        let (_daemon, connection) = mock_daemon("power-saver").await;
+        let profiles = profiles(&connection);
+
+        tokio::task::LocalSet::new()
+            .run_until(async {
+                assert_eq!(
+                    unplug_and_plug(&profiles, &connection, None).await,
+                    ("power-saver".to_string(), "power-saver".to_string())
+                );
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn previews_do_not_touch_the_daemon() {
+        let (_daemon, connection) = mock_daemon("balanced").await;
+        let config = PowerProfileConfig {
+            enabled: true,
+            ..PowerProfileConfig::default()
+        };
+        let profiles = Rc::new(PowerProfiles::new(&config, Rc::new(SystemClock), false));
+        *profiles.connection.borrow_mut() = Some(connection.clone());
+
+        tokio::task::LocalSet::new()
+            .run_until(async {
+                profiles.on_battery(25);
+                profiles.idle().await;
+                assert_eq!(active_profile(&connection).await.unwrap(), "balanced");
+            })
+            .await;
+    }
+}
@@ -0,0 +1,199 @@
+use crate::notifier::Event;
+use chrono::{Datelike, NaiveTime, Weekday};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+const DND_PROBE_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QuietConfig {
+    pub schedule: Vec<QuietWindow>,
+    /// Follow the desktop's Do-Not-Disturb state
+    pub detect_dnd: bool,
+    /// Let critical events through while quiet
+    pub allow_critical: bool,
+}
+
+impl Default for QuietConfig {
+    fn default() -> Self {
+        QuietConfig {
+            schedule: Vec::new(),
+            detect_dnd: true,
+            allow_critical: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct QuietWindow {
+    /// `HH:MM`, may be later than `end` to span midnight
+    pub start: String,
+    pub end: String,
+    /// Day names, `weekdays` or `weekends`, every day when empty
+    #[serde(default)]
+    pub days: Vec<String>,
+}
+
+fn parse_time(value: &str) -> Result<NaiveTime, String> {
+    NaiveTime::parse_from_str(value, "%H:%M").map_err(|_| format!("invalid time '{value}'"))
+}
+
+fn parse_days(days: &[String]) -> Result<Vec<Weekday>, String> {
+    let mut out = Vec::new();
+
+    for day in days {
+        match day.to_lowercase().as_str() {
+            "weekdays" => out.extend([
+                Weekday::Mon,
+                Weekday::Tue,
+                Weekday::Wed,
+                Weekday::Thu,
+                Weekday::Fri,
+            ]),
+            "weekends" => out.extend([Weekday::Sat, Weekday::Sun]),
+            name => out.push(name.parse().map_err(|_| format!("invalid day '{day}'"))?),
+        }
+    }
+
+    Ok(out)
+}
+
+impl QuietWindow {
+    pub fn validate(&self) -> Result<(), String> {
+        parse_time(&self.start)?;
+        parse_time(&self.end)?;
+        parse_days(&self.days)?;
+
+        Ok(())
+    }
+
+    /// A window spanning midnight belongs to the day it starts on
+    pub fn contains(&self, now: chrono::DateTime<chrono::Local>) -> bool {
+        let (Ok(start), Ok(end), Ok(days)) = (
+            parse_time(&self.start),
+            parse_time(&self.end),
+            parse_days(&self.days),
+        ) else {
+            return false;
+        };
+
+        let on = |day: Weekday| days.is_empty() || days.contains(&day);
+        let time = now.time();
+        let today = now.weekday();
+
+        if start <= end {
+            on(today) && time >= start && time < end
+        } else {
+            (on(today) && time >= start) || (on(today.pred()) && time < end)
+        }
+    }
+}
+
+impl QuietConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        self.schedule.iter().try_for_each(QuietWindow::validate)
+    }
+
+    pub fn in_schedule(&self, now: chrono::DateTime<chrono::Local>) -> bool {
+        self.schedule.iter().any(|window| window.contains(now))
+    }
+
+    pub fn lets_through(&self, event: Event) -> bool {
+        self.allow_critical && event.is_critical()
+    }
+}
+
+async fn probe(program: &str, args: &[&str]) -> Option<String> {
+    let output = tokio::time::timeout(
+        DND_PROBE_TIMEOUT,
+        tokio::process::Command::new(program).args(args).output(),
+    )
+    .await
+    .ok()?
+    .ok()?;
+
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Do-Not-Disturb state of dunst, GNOME or mako, whichever answers
+pub async fn desktop_dnd() -> bool {
+    if let Some(paused) = probe("dunstctl", &["is-paused"]).await {
+        if paused == "true" {
+            log::debug!("dunst is paused");
+            return true;
+        }
+    }
+
+    if let Some(banners) = probe(
+        "gsettings",
+        &["get", "org.gnome.desktop.notifications", "show-banners"],
+    )
+    .await
+    {
+        if banners == "false" {
+            log::debug!("GNOME banners are off");
+            return true;
+        }
+    }
+
+    if let Some(modes) = probe("makoctl", &["mode"]).await {
+        if modes.lines().any(|mode| mode == "do-not-disturb") {
+            log::debug!("mako is in do-not-disturb mode");
+            return true;
+        }
+    }
+
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn night_on_weekdays() -> QuietWindow {
+        QuietWindow {
+            start: "22:00".to_string(),
+            end: "07:00".to_string(),
+            days: vec!["weekdays".to_string()],
+        }
+    }
+
+    /// 2026-10-16 is a Friday
+    fn at(day: u32, hour: u32) -> chrono::DateTime<chrono::Local> {
+        let time = chrono::NaiveDate::from_ymd_opt(2026, 10, day)
+            .unwrap()
+            .and_hms_opt(hour, 0, 0)
+            .unwrap();
+        chrono::Local.from_local_datetime(&time).earliest().unwrap()
+    }
+
+    #[test]
+    fn overnight_window_belongs_to_its_start_day() {
+        let window = night_on_weekdays();
+
+        assert_eq!(at(16, 23).weekday(), Weekday::Fri);
+        assert!(window.contains(at(16, 23)));
+        // Friday night runs into Saturday morning
+        assert!(window.contains(at(17, 6)));
+        // Sunday night is not a weekday
+        assert!(!window.contains(at(19, 6)));
+        assert!(!window.contains(at(16, 12)));
+    }
+
+    #[test]
+    fn rejects_unknown_day() {
+        let window = QuietWindow {
+            days: vec!["someday".to_string()],
+            ..night_on_weekdays()
+        };
+
+        assert!(window.validate().is_err());
+        assert!(!window.contains(at(16, 23)));
+    }
+}
@@ -0,0 +1,39 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Repeats of the low battery alert while it stays unanswered
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReminderConfig {
+    /// Minutes between reminders, 0 turns them off
+    pub interval_minutes: u32,
+    /// Remind on every drop by this many percent, 0 turns it off
+    pub every_percent: u32,
+    /// Most reminders per alert, 0 for no limit
+    pub max_repeats: u32,
+}
+
+impl Default for ReminderConfig {
+    fn default() -> Self {
+        ReminderConfig {
+            interval_minutes: 0,
+            every_percent: 0,
+            max_repeats: 3,
+        }
+    }
+}
+
+impl ReminderConfig {
+    pub fn interval(&self) -> Option<Duration> {
+        (self.interval_minutes > 0)
+            .then(|| Duration::from_secs(u64::from(self.interval_minutes) * 60))
+    }
+
+    pub fn allows(&self, repeats: u32) -> bool {
+        self.max_repeats == 0 || repeats < self.max_repeats
+    }
+
+    pub fn dropped_enough(&self, reminded_at: u32, percent: u32) -> bool {
+        self.every_percent > 0 && percent + self.every_percent <= reminded_at
+    }
+}
@@ -0,0 +1,168 @@
+use crate::{
+    helper,
+    notifier::{DaemonEvent, Notifier},
+};
+use sd_notify::NotifyState;
+use std::{path::PathBuf, process::exit, rc::Rc, time::Duration};
+
+const UNIT_NAME: &str = "battery-notify.service";
+
+pub fn unit_path() -> Option<PathBuf> {
+    Some(
+        helper::xdg_config_home()?
+            .join("systemd/user")
+            .join(UNIT_NAME),
+    )
+}
+
+/// Quotes a word for an `Exec*=` line, escaping what systemd would otherwise
+/// expand: `%` specifiers and `$` variables
+fn quote_exec_arg(arg: &str) -> String {
+    let mut out = String::from('"');
+
+    for c in arg.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            '%' => out.push_str("%%"),
+            '$' => out.push_str("$$"),
+            _ => out.push(c),
+        }
+    }
+
+    out.push('"');
+    out
+}
+
+pub fn unit_file() -> String {
+    let exe = match std::env::current_exe() {
+        Ok(out) => out,
+        Err(e) => {
+            log::error!("cannot locate executable: {e}");
+            exit(1);
+        }
+    };
+
+    format!(
+        "[Unit]
+Description=Battery level and charger notifications
+PartOf=graphical-session.target
+After=graphical-session.target
+
+[Service]
+Type=notify
+NotifyAccess=main
+ExecStart={} run
+Restart=on-failure
+WatchdogSec=30
+
+[Install]
+WantedBy=graphical-session.target
+",
+        quote_exec_arg(&exe.to_string_lossy())
+    )
+}
+
+pub fn install(print: bool, force: bool) {
+    let unit = unit_file();
+
+    if print {
+        print!("{unit}");
+        return;
+    }
+
+    let Some(path) = unit_path() else {
+        log::error!("cannot locate the systemd user unit directory, HOME is not set");
+        exit(1);
+    };
+
+    if path.exists() && !force {
+        log::error!(
+            "{} already exists, use --force to overwrite",
+            path.display()
+        );
+        exit(1);
+    }
+
+    if let Err(e) = path
+        .parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|_| std::fs::write(&path, unit))
+    {
+        log::error!("{}: {e}", path.display());
+        exit(1);
+    }
+
+    println!("installed {}", path.display());
+    println!("enable it with: systemctl --user daemon-reload && systemctl --user enable --now {UNIT_NAME}");
+}
+
+fn notify(state: &[NotifyState]) {
+    if let Err(e) = sd_notify::notify(false, state) {
+        log::debug!("sd_notify error: {e}");
+    }
+}
+
+fn update_status(notifier: &Notifier) {
+    let state = notifier.state();
+    let status = format!("BAT{} {}% {}", state.battery, state.percent, state.status);
+    notify(&[NotifyState::Status(&status)]);
+}
+
+pub async fn run(notifier: &Rc<Notifier>) {
+    let shutdown = notifier.shutdown_token();
+    let mut events = notifier.subscribe();
+    let mut armed = false;
+
+    let mut watchdog_usec = 0;
+    let mut watchdog = sd_notify::watchdog_enabled(false, &mut watchdog_usec).then(|| {
+        log::debug!("systemd watchdog enabled, timeout {watchdog_usec}us");
+        tokio::time::interval(Duration::from_micros(watchdog_usec / 2))
+    });
+
+    loop {
+        tokio::select! {
+            _ = notifier.wait_armed(), if !armed => {
+                armed = true;
+                notify(&[NotifyState::Ready]);
+                update_status(notifier);
+                log::debug!("watchers armed, readiness sent");
+            }
+
+            _ = async {
+                match watchdog.as_mut() {
+                    Some(interval) => interval.tick().await,
+                    None => std::future::pending().await,
+                }
+            } => notify(&[NotifyState::Watchdog]),
+
+            event = events.recv() => {
+                if let Ok(DaemonEvent::Percent { .. } | DaemonEvent::Status { .. }) = event {
+                    update_status(notifier);
+                }
+            }
+
+            _ = shutdown.cancelled() => {
+                notify(&[NotifyState::Stopping]);
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quotes_exec_path() {
+        assert_eq!(
+            quote_exec_arg("/home/me/my apps/battery-notify"),
+            "\"/home/me/my apps/battery-notify\""
+        );
+        assert_eq!(
+            quote_exec_arg("/opt/100%/\"$HOME\"\\bin"),
+            "\"/opt/100%%/\\\"$$HOME\\\"\\\\bin\""
+        );
+    }
+}
@@ -0,0 +1,364 @@
+use crate::{battery::ChargeStatus, config::Config, helper, notifier::Notifier};
+use serde::Deserialize;
+use std::{
+    os::unix::fs::DirBuilderExt,
+    path::{Path, PathBuf},
+    process::exit,
+    rc::Rc,
+    time::Duration,
+};
+use tokio_util::sync::CancellationToken;
+
+// the 2s poll watchers need a moment to pick up the last write
+const WATCH_DELAY: Duration = Duration::from_secs(3);
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Scenario {
+    pub from: u32,
+    pub status: ChargeStatus,
+    pub online: bool,
+    pub steps: Vec<Step>,
+}
+
+impl Default for Scenario {
+    fn default() -> Self {
+        Scenario {
+            from: 40,
+            status: ChargeStatus::Discharging,
+            online: false,
+            steps: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Step {
+    /// Seconds to wait after the previous step
+    pub wait: f64,
+    pub percent: Option<u32>,
+    pub status: Option<ChargeStatus>,
+    pub online: Option<bool>,
+}
+
+/// Percent per second from e.g. `-10%/min`, `2%/s` or `-30%/h`
+pub fn parse_rate(value: &str) -> Result<f64, String> {
+    let invalid = || format!("invalid rate '{value}', expected e.g. -10%/min");
+
+    let (amount, unit) = value.split_once("%/").ok_or_else(invalid)?;
+    let amount = amount.parse::<f64>().map_err(|_| invalid())?;
+
+    let seconds = match unit {
+        "s" | "sec" => 1.0,
+        "m" | "min" => 60.0,
+        "h" | "hour" => 3600.0,
+        _ => return Err(invalid()),
+    };
+
+    if amount == 0.0 || !amount.is_finite() {
+        return Err(invalid());
+    }
+
+    Ok(amount / seconds)
+}
+
+pub fn parse_percent(value: &str) -> Result<u32, String> {
+    match value.trim_end_matches('%').parse::<u32>() {
+        Ok(percent) if percent <= 100 => Ok(percent),
+        _ => Err(format!("invalid percentage '{value}'")),
+    }
+}
+
+impl Scenario {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        let scenario: Scenario =
+            toml::from_str(&text).map_err(|e| format!("{}: {e}", path.display()))?;
+
+        scenario
+            .validate()
+            .map_err(|e| format!("{}: {e}", path.display()))?;
+
+        Ok(scenario)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.from > 100 {
+            return Err(format!("from must be within 0-100, got {}", self.from));
+        }
+
+        for (index, step) in self.steps.iter().enumerate() {
+            match step.percent {
+                Some(percent) if percent > 100 => {
+                    return Err(format!(
+                        "step {} percent must be within 0-100, got {percent}",
+                        index + 1
+                    ))
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Drain (or charge) at a constant rate, optionally plugging in at a level,
+    /// and charge to full once plugged
+    pub fn from_rate(from: u32, rate: f64, plug_at: Option<u32>) -> Self {
+        let wait = 1.0 / rate.abs();
+        let mut steps = Vec::new();
+        let mut percent = from;
+
+        if rate < 0.0 {
+            let stop = plug_at.unwrap_or(0);
+
+            while percent > stop {
+                percent -= 1;
+                steps.push(Step {
+                    wait,
+                    percent: Some(percent),
+                    ..Step::default()
+                });
+            }
+
+            if plug_at.is_none() {
+                return Scenario {
+                    from,
+                    steps,
+                    ..Scenario::default()
+                };
+            }
+
+            steps.push(Step {
+                status: Some(ChargeStatus::Charging),
+                online: Some(true),
+                ..Step::default()
+            });
+        }
+
+        while percent < 100 {
+            percent += 1;
+            steps.push(Step {
+                wait,
+                percent: Some(percent),
+                ..Step::default()
+            });
+        }
+
+        steps.push(Step {
+            status: Some(ChargeStatus::NotCharging),
+            ..Step::default()
+        });
+
+        let charging = rate > 0.0;
+
+        Scenario {
+            from,
+            status: if charging {
+                ChargeStatus::Charging
+            } else {
+                ChargeStatus::Discharging
+            },
+            online: charging,
+            steps,
+        }
+    }
+}
+
+struct Tree {
+    dir: PathBuf,
+    battery: PathBuf,
+    adapter: PathBuf,
+}
+
+impl Tree {
+    fn create(battery_id: u32, scenario: &Scenario) -> std::io::Result<Self> {
+        let dir = helper::ensure_runtime_dir()?.join(format!("sim-{}", std::process::id()));
+
+        // a fresh private dir, never one that is already there
+        std::fs::DirBuilder::new().mode(0o700).create(&dir)?;
+
+        let tree = Tree {
+            battery: dir.join(format!("BAT{battery_id}")),
+            adapter: dir.join("AC"),
+            dir,
+        };
+
+        std::fs::create_dir(&tree.battery)?;
+        std::fs::create_dir(&tree.adapter)?;
+
+        tree.write(&tree.battery, "type", "Battery")?;
+        tree.write(&tree.battery, "present", "1")?;
+        tree.write(&tree.adapter, "type", "Mains")?;
+
+        tree.apply(&Step {
+            percent: Some(scenario.from),
+            status: Some(scenario.status),
+            online: Some(scenario.online),
+            ..Step::default()
+        })?;
+
+        Ok(tree)
+    }
+
+    fn write(&self, dir: &Path, attribute: &str, value: &str) -> std::io::Result<()> {
+        std::fs::write(dir.join(attribute), format!("{value}\n"))
+    }
+
+    fn apply(&self, step: &Step) -> std::io::Result<()> {
+        if let Some(online) = step.online {
+            self.write(&self.adapter, "online", if online { "1" } else { "0" })?;
+        }
+
+        if let Some(status) = step.status {
+            self.write(&self.battery, "status", &status.to_string())?;
+        }
+
+        if let Some(percent) = step.percent {
+            self.write(&self.battery, "capacity", &percent.to_string())?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for Tree {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_dir_all(&self.dir) {
+            log::debug!("cannot remove {}: {e}", self.dir.display());
+        }
+    }
+}
+
+async fn drive(tree: &Tree, scenario: &Scenario, settle: Duration, shutdown: &CancellationToken) {
+    for step in &scenario.steps {
+        tokio::select! {
+            _ = tokio::time::sleep(Duration::from_secs_f64(step.wait.max(0.0))) => {}
+            _ = shutdown.cancelled() => return,
+        }
+
+        log::debug!("simulate step: {:?}", step);
+
+        if let Err(e) = tree.apply(step) {
+            log::error!("simulated power supply: {e}");
+            break;
+        }
+    }
+
+    tokio::select! {
+        _ = tokio::time::sleep(WATCH_DELAY + settle) => log::info!("simulation finished"),
+        _ = shutdown.cancelled() => {}
+    }
+
+    shutdown.cancel();
+}
+
+pub async fn run(config: &Config, scenario: &Scenario) {
+    let tree = match Tree::create(config.battery_id, scenario) {
+        Ok(out) => out,
+        Err(e) => {
+            log::error!("cannot create simulated power supply: {e}");
+            exit(1);
+        }
+    };
+
+    log::info!("simulated power supply at {}", tree.dir.display());
+    crate::battery::set_power_supply_dir(tree.dir.clone());
+
+    // a preview, hooks are printed rather than run
+    let notifier = Rc::new(Notifier::new(config, false));
+    let shutdown = notifier.shutdown_token();
+
+    let local = tokio::task::LocalSet::new();
+    local
+        .run_until(async {
+            tokio::join!(
+                crate::watch_until_shutdown(notifier.make_percent_watcher(), &shutdown),
+                crate::watch_until_shutdown(notifier.make_status_watcher(), &shutdown),
+                notifier.low_battery_notification(scenario.from),
+                drive(&tree, scenario, config.debounce.max_delay(), &shutdown),
+                helper::shutdown_signal(&shutdown)
+            )
+        })
+        .await;
+
+    notifier.close_notifications();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn percents(scenario: &Scenario) -> Vec<u32> {
+        scenario
+            .steps
+            .iter()
+            .filter_map(|step| step.percent)
+            .collect()
+    }
+
+    #[test]
+    fn parses_rates() {
+        assert_eq!(parse_rate("-10%/min"), Ok(-10.0 / 60.0));
+        assert_eq!(parse_rate("2%/s"), Ok(2.0));
+        assert_eq!(parse_rate("-36%/h"), Ok(-0.01));
+
+        for invalid in ["10", "0%/min", "-10%/day", "x%/s", "inf%/s"] {
+            assert!(parse_rate(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn drains_and_plugs_in() {
+        let scenario = Scenario::from_rate(30, -1.0, Some(28));
+
+        assert_eq!(scenario.status, ChargeStatus::Discharging);
+        assert_eq!(&percents(&scenario)[..3], [29, 28, 29]);
+        assert_eq!(scenario.steps[2].status, Some(ChargeStatus::Charging));
+        assert_eq!(
+            scenario.steps.last().unwrap().status,
+            Some(ChargeStatus::NotCharging)
+        );
+    }
+
+    #[test]
+    fn plugs_in_at_once_from_below_the_plug_level() {
+        for plug_at in [40, 50] {
+            let scenario = Scenario::from_rate(40, -1.0, Some(plug_at));
+
+            assert_eq!(scenario.steps[0].status, Some(ChargeStatus::Charging));
+            assert_eq!(percents(&scenario), (41..=100).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn drains_to_empty_without_a_plug_level() {
+        let scenario = Scenario::from_rate(3, -0.5, None);
+
+        assert_eq!(percents(&scenario), [2, 1, 0]);
+        assert!(scenario.steps.iter().all(|step| step.wait == 2.0));
+    }
+
+    #[test]
+    fn charges_at_a_positive_rate() {
+        let scenario = Scenario::from_rate(97, 1.0, None);
+
+        assert_eq!(scenario.status, ChargeStatus::Charging);
+        assert!(scenario.online);
+        assert_eq!(percents(&scenario), [98, 99, 100]);
+        assert_eq!(
+            scenario.steps.last().unwrap().status,
+            Some(ChargeStatus::NotCharging)
+        );
+    }
+
+    #[test]
+    fn rejects_percent_above_100() {
+        let scenario: Scenario = toml::from_str("from = 50\n[[steps]]\npercent = 101").unwrap();
+        assert!(scenario.validate().is_err());
+
+        let scenario: Scenario = toml::from_str("from = 150").unwrap();
+        assert!(scenario.validate().is_err());
+    }
+}
@@ -0,0 +1,211 @@
+use crate::{battery, config::Config};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, path::Path, process::exit};
+
+#[derive(Debug, Clone, clap::Subcommand)]
+pub enum ThresholdsCommand {
+    /// Print the charge thresholds and charge behaviour
+    Get {
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Start charging below START and stop at END percent
+    Set { start: u32, end: u32 },
+
+    /// Apply a threshold profile from the config, e.g. travel or desk
+    Profile { name: String },
+
+    /// Set the charge behaviour, e.g. auto, inhibit-charge or force-discharge
+    Behaviour { mode: String },
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ThresholdProfile {
+    pub start: u32,
+    pub end: u32,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ThresholdsConfig {
+    /// Extra profiles, on top of the built-in travel and desk
+    pub profiles: HashMap<String, ThresholdProfile>,
+}
+
+impl ThresholdsConfig {
+    pub fn profile(&self, name: &str) -> Option<ThresholdProfile> {
+        if let Some(profile) = self.profiles.get(name) {
+            return Some(*profile);
+        }
+
+        match name {
+            "travel" => Some(ThresholdProfile {
+                start: 95,
+                end: 100,
+            }),
+            "desk" => Some(ThresholdProfile { start: 75, end: 80 }),
+            _ => None,
+        }
+    }
+}
+
+fn validate(start: u32, end: u32) -> Result<(), String> {
+    if end == 0 || end > 100 {
+        return Err(format!("end threshold must be within 1-100, got {end}"));
+    }
+
+    if start >= end {
+        return Err(format!(
+            "start threshold {start} must be below the end threshold {end}"
+        ));
+    }
+
+    Ok(())
+}
+
+fn is_root() -> bool {
+    unsafe { libc::geteuid() == 0 }
+}
+
+/// Where the polkit action in data/org.batterynotify.thresholds.policy
+/// expects the helper. Nothing else is run as root: a helper anywhere else
+/// would fall back to the generic pkexec action.
+const HELPER_PATH: &str = "/usr/libexec/battery-notify-helper";
+
+/// Hands the write to the helper through pkexec, so polkit asks for
+/// authorisation for just that instead of the daemon running as root
+fn escalate(battery_id: u32, args: &[String]) -> ! {
+    if !Path::new(HELPER_PATH).exists() {
+        log::error!(
+            "permission denied, and battery-notify-helper is not installed to {HELPER_PATH}; \
+             install it with data/org.batterynotify.thresholds.policy to \
+             /usr/share/polkit-1/actions/, see src/bin/battery-notify-helper.rs"
+        );
+        exit(1);
+    }
+
+    log::debug!("permission denied, retrying through pkexec {HELPER_PATH}");
+
+    match std::process::Command::new("pkexec")
+        .arg(HELPER_PATH)
+        .arg(battery_id.to_string())
+        .args(args)
+        .status()
+    {
+        Ok(status) => exit(status.code().unwrap_or(1)),
+        Err(e) => {
+            log::error!("cannot run pkexec: {e}");
+            exit(1);
+        }
+    }
+}
+
+/// Writes the attributes in order, escalating on the first permission error
+fn write(battery_id: u32, values: &[(&str, String)], args: &[String]) {
+    let name = format!("BAT{battery_id}");
+
+    for (attribute, value) in values {
+        match battery::write_attribute(&name, attribute, value) {
+            Ok(_) => log::debug!("{name}/{attribute} = {value}"),
+            Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied && !is_root() => {
+                escalate(battery_id, args)
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                log::error!("{name} does not support {attribute}");
+                exit(1);
+            }
+            Err(e) => {
+                log::error!("{name}/{attribute}: {e}");
+                exit(1);
+            }
+        }
+    }
+}
+
+fn set(battery_id: u32, start: u32, end: u32) {
+    if let Err(e) = validate(start, end) {
+        log::error!("{e}");
+        exit(1);
+    }
+
+    // drivers reject a start above the current end, so order the writes to
+    // keep start below end at every step
+    let current = battery::read_thresholds(battery_id);
+    let start_value = (battery::START_THRESHOLD, start.to_string());
+    let end_value = (battery::END_THRESHOLD, end.to_string());
+
+    let values = if current.end.is_some_and(|current_end| start >= current_end) {
+        [end_value, start_value]
+    } else {
+        [start_value, end_value]
+    };
+
+    write(
+        battery_id,
+        &values,
+        &["set".to_string(), start.to_string(), end.to_string()],
+    );
+
+    println!("BAT{battery_id}: charge between {start}% and {end}%");
+}
+
+fn print(battery_id: u32, json: bool) {
+    let thresholds = battery::read_thresholds(battery_id);
+
+    if json {
+        match serde_json::to_string_pretty(&thresholds) {
+            Ok(out) => println!("{out}"),
+            Err(e) => {
+                log::error!("json output error: {e}");
+                exit(1);
+            }
+        }
+        return;
+    }
+
+    let percent = |value: Option<u32>| value.map_or("-".to_string(), |v| format!("{v}%"));
+
+    println!(
+        "BAT{battery_id}: start {}, end {}, behaviour {}",
+        percent(thresholds.start),
+        percent(thresholds.end),
+        thresholds.behaviour.as_deref().unwrap_or("-")
+    );
+}
+
+pub fn run(config: &Config, command: ThresholdsCommand) {
+    let battery_id = config.battery_id;
+
+    match command {
+        ThresholdsCommand::Get { json } => print(battery_id, json),
+        ThresholdsCommand::Set { start, end } => set(battery_id, start, end),
+        ThresholdsCommand::Profile { name } => match config.thresholds.profile(&name) {
+            Some(profile) => set(battery_id, profile.start, profile.end),
+            None => {
+                log::error!("unknown threshold profile '{name}'");
+                exit(1);
+            }
+        },
+        ThresholdsCommand::Behaviour { mode } => {
+            let supported = battery::read_thresholds(battery_id).behaviours;
+
+            if !supported.contains(&mode) {
+                log::error!(
+                    "BAT{battery_id} does not support charge behaviour '{mode}', supported: {}",
+                    supported.join(", ")
+                );
+                exit(1);
+            }
+
+            write(
+                battery_id,
+                &[(battery::CHARGE_BEHAVIOUR, mode.clone())],
+                &["behaviour".to_string(), mode.clone()],
+            );
+
+            println!("BAT{battery_id}: charge behaviour {mode}");
+        }
+    }
+}
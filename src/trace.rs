@@ -0,0 +1,253 @@
+use crate::{
+    battery,
+    clock::{Clock, ManualClock},
+    config::Config,
+    notifier::Notifier,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader, Write},
+    path::Path,
+    process::exit,
+    rc::Rc,
+    time::Duration,
+};
+
+const RECORD_INTERVAL: Duration = Duration::from_millis(2000);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Reading {
+    Percent { percent: u32 },
+    Status { status: battery::ChargeStatus },
+    Adapter { name: String, online: bool },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TraceEntry {
+    pub time: chrono::DateTime<chrono::Local>,
+    #[serde(flatten)]
+    pub reading: Reading,
+}
+
+/// Replay speed factor, `max` replays without waiting
+pub fn parse_speed(value: &str) -> Result<f64, String> {
+    if value == "max" {
+        return Ok(f64::INFINITY);
+    }
+
+    match value.trim_end_matches('x').parse::<f64>() {
+        Ok(speed) if speed > 0.0 && speed.is_finite() => Ok(speed),
+        _ => Err(format!("invalid speed '{value}', expected e.g. 60x or max")),
+    }
+}
+
+fn readings(battery_id: u32) -> Vec<Reading> {
+    let mut out = Vec::new();
+
+    if let Ok(percent) = battery::Battery::get_live_percent(battery_id) {
+        out.push(Reading::Percent { percent });
+    }
+
+    if let Ok(status) = battery::Battery::get_live_status(battery_id) {
+        out.push(Reading::Status { status });
+    }
+
+    for supply in battery::power_supplies().unwrap_or_default() {
+        if let (false, Some(online)) = (supply.is_battery(), supply.online) {
+            out.push(Reading::Adapter {
+                name: supply.name,
+                online,
+            });
+        }
+    }
+
+    out
+}
+
+fn reading_key(reading: &Reading) -> String {
+    match reading {
+        Reading::Percent { .. } => "percent".to_string(),
+        Reading::Status { .. } => "status".to_string(),
+        Reading::Adapter { name, .. } => format!("adapter:{name}"),
+    }
+}
+
+pub async fn record(config: &Config, path: &Path) {
+    let mut file = match std::fs::File::create(path) {
+        Ok(out) => out,
+        Err(e) => {
+            log::error!("{}: {e}", path.display());
+            exit(1);
+        }
+    };
+
+    let mut last: HashMap<String, String> = HashMap::new();
+    let mut interval = tokio::time::interval(RECORD_INTERVAL);
+
+    eprintln!(
+        "recording BAT{} to {}, press ctrl-c to stop",
+        config.battery_id,
+        path.display()
+    );
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = tokio::signal::ctrl_c() => break,
+        }
+
+        let time = chrono::Local::now();
+
+        for reading in readings(config.battery_id) {
+            let value = serde_json::to_string(&reading).unwrap_or_default();
+
+            if last.get(&reading_key(&reading)) == Some(&value) {
+                continue;
+            }
+
+            last.insert(reading_key(&reading), value);
+
+            let line = serde_json::to_string(&TraceEntry { time, reading }).unwrap_or_default();
+
+            if let Err(e) = writeln!(file, "{line}").and_then(|_| file.flush()) {
+                log::error!("{}: {e}", path.display());
+                exit(1);
+            }
+        }
+    }
+}
+
+fn load_trace(path: &Path) -> Vec<TraceEntry> {
+    let file = match std::fs::File::open(path) {
+        Ok(out) => out,
+        Err(e) => {
+            log::error!("{}: {e}", path.display());
+            exit(1);
+        }
+    };
+
+    let mut entries = Vec::new();
+
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        let line = match line {
+            Ok(out) => out,
+            Err(e) => {
+                log::error!("{}: {e}", path.display());
+                exit(1);
+            }
+        };
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        match serde_json::from_str(&line) {
+            Ok(entry) => entries.push(entry),
+            Err(e) => {
+                log::error!("{}:{}: {e}", path.display(), index + 1);
+                exit(1);
+            }
+        }
+    }
+
+    entries
+}
+
+fn initial_battery(config: &Config, entries: &[TraceEntry]) -> battery::Battery {
+    let mut battery = battery::Battery {
+        id: config.battery_id,
+        percent: 100,
+        status: battery::ChargeStatus::Unknown,
+    };
+
+    if let Some(percent) = entries.iter().find_map(|entry| match entry.reading {
+        Reading::Percent { percent } => Some(percent),
+        _ => None,
+    }) {
+        battery.percent = percent;
+    }
+
+    if let Some(status) = entries.iter().find_map(|entry| match entry.reading {
+        Reading::Status { status } => Some(status),
+        _ => None,
+    }) {
+        battery.status = status;
+    }
+
+    battery
+}
+
+/// Moves the clock to `time`, waiting in real time for each deadline on the
+/// way so notifications keep their pace
+async fn advance(clock: &ManualClock, time: chrono::DateTime<chrono::Local>, speed: f64) {
+    loop {
+        let next = clock
+            .next_deadline()
+            .filter(|deadline| *deadline <= time)
+            .unwrap_or(time);
+
+        if speed.is_finite() {
+            let delay = (next - clock.now()).to_std().unwrap_or_default();
+            tokio::time::sleep(delay.div_f64(speed)).await;
+        }
+
+        clock.advance_to(next).await;
+
+        if next == time {
+            return;
+        }
+    }
+}
+
+pub async fn replay(config: &Config, path: &Path, speed: f64, show: bool) {
+    let entries = load_trace(path);
+
+    let Some(first) = entries.first() else {
+        log::warn!("{} holds no readings", path.display());
+        return;
+    };
+
+    // every wait in the notifier runs on the manual clock, which jumps ahead
+    // in trace time; the speed only paces the replay in real time
+    let clock = Rc::new(ManualClock::new(first.time));
+
+    let notifier = Rc::new(Notifier::with_state(
+        config,
+        initial_battery(config, &entries),
+        clock.clone(),
+        !show,
+        false,
+        true,
+    ));
+
+    let local = tokio::task::LocalSet::new();
+    local
+        .run_until(async {
+            for entry in &entries {
+                advance(&clock, entry.time, speed).await;
+
+                match &entry.reading {
+                    Reading::Percent { percent } => notifier.handle_percent(*percent),
+                    Reading::Status { status } => notifier.handle_status(*status),
+                    Reading::Adapter { name, online } => notifier.handle_adapter(name, *online),
+                }
+
+                tokio::task::yield_now().await;
+            }
+
+            // let the last status change settle
+            let settled = clock.now()
+                + chrono::Duration::from_std(config.debounce.max_delay())
+                    .unwrap_or(chrono::Duration::zero());
+            advance(&clock, settled, speed).await;
+            notifier.wait_idle().await;
+
+            notifier.shutdown_token().cancel();
+            tokio::task::yield_now().await;
+        })
+        .await;
+
+    notifier.close_notifications();
+}